@@ -0,0 +1,153 @@
+use std::path::Path;
+
+use crate::instruments::{Instrument, InstrumentWithEq};
+use crate::reader::*;
+use crate::scale::Scale;
+use crate::songs::Song;
+use crate::theme::Theme;
+use crate::version::{read_header, Version};
+
+/// Kind of file handled by the library
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum M8FileKind {
+    Instrument,
+    Song,
+    Theme,
+    Scale,
+}
+
+impl M8FileKind {
+    /// Expected kind of a file given its extension, as used by the M8
+    pub fn from_extension(ext: &str) -> Option<M8FileKind> {
+        match ext.to_ascii_lowercase().as_str() {
+            "m8i" => Some(M8FileKind::Instrument),
+            "m8s" => Some(M8FileKind::Song),
+            "m8t" => Some(M8FileKind::Theme),
+            "m8n" => Some(M8FileKind::Scale),
+            _ => None,
+        }
+    }
+}
+
+/// Any file parsed by the library
+#[derive(PartialEq, Clone)]
+pub enum M8File {
+    Instrument(Box<InstrumentWithEq>),
+    Song(Box<Song>),
+    Theme(Theme),
+    Scale(Scale),
+}
+
+impl M8File {
+    pub fn kind(&self) -> M8FileKind {
+        match self {
+            M8File::Instrument(_) => M8FileKind::Instrument,
+            M8File::Song(_) => M8FileKind::Song,
+            M8File::Theme(_) => M8FileKind::Theme,
+            M8File::Scale(_) => M8FileKind::Scale,
+        }
+    }
+}
+
+/// Detect the kind of an M8 file, from its extension if it is a known
+/// one, or from the size of its content otherwise.
+pub fn identify(path: &Path, bytes: &[u8]) -> Option<M8FileKind> {
+    if let Some(kind) = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .and_then(M8FileKind::from_extension)
+    {
+        return Some(kind);
+    }
+
+    read_header(&mut Reader::new(bytes.to_vec())).ok()?;
+    let len = bytes.len() - Version::SIZE;
+    if len >= Song::SIZE_PRIOR_TO_2_5 {
+        Some(M8FileKind::Song)
+    } else if len >= Instrument::INSTRUMENT_MEMORY_SIZE {
+        Some(M8FileKind::Instrument)
+    } else if len >= Scale::SIZE {
+        Some(M8FileKind::Scale)
+    } else if len >= Theme::SIZE {
+        Some(M8FileKind::Theme)
+    } else {
+        None
+    }
+}
+
+/// Load any M8 file, detecting its kind with [`identify`]
+pub fn open(path: &Path) -> M8Result<M8File> {
+    let bytes = std::fs::read(path).map_err(|e| {
+        ParseError::new(
+            ParseErrorKind::Io,
+            format!("Could not read {}: {}", path.display(), e),
+        )
+    })?;
+
+    let kind = identify(path, &bytes).ok_or_else(|| {
+        ParseError::new(
+            ParseErrorKind::UnknownFileType,
+            format!(
+                "{} is not a M8 instrument, song, theme or scale",
+                path.display()
+            ),
+        )
+    })?;
+
+    let mut reader = &bytes[..];
+    Ok(match kind {
+        M8FileKind::Instrument => M8File::Instrument(Box::new(Instrument::read(&mut reader)?)),
+        M8FileKind::Song => M8File::Song(Box::new(Song::read(&mut reader)?)),
+        M8FileKind::Theme => M8File::Theme(Theme::read(&mut reader)?),
+        M8FileKind::Scale => M8File::Scale(Scale::read(&mut reader)?),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::file::*;
+
+    /// File of the temporary directory, removed when dropped
+    struct TempFile(std::path::PathBuf);
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    /// Write a file with a valid header, its name prefixed by the process
+    /// id so concurrent test runs don't share it.
+    fn synthetic_file(name: &str, payload_size: usize) -> TempFile {
+        let mut bytes = b"M8VERSION\0".to_vec();
+        bytes.extend([0x10, 4, 0, 0]);
+        bytes.resize(Version::SIZE + payload_size, 0);
+
+        let name = format!("m8-files-{}-{}", std::process::id(), name);
+        let path = std::env::temp_dir().join(name);
+        std::fs::write(&path, bytes).unwrap();
+        TempFile(path)
+    }
+
+    #[test]
+    fn open_detects_each_kind() {
+        let song = open(Path::new("./examples/songs/V4EMPTY.m8s")).unwrap();
+        assert_eq!(song.kind(), M8FileKind::Song);
+
+        let instr = open(Path::new("./examples/instruments/FMDUBSTAB_4_1.m8i")).unwrap();
+        assert_eq!(instr.kind(), M8FileKind::Instrument);
+
+        let theme = synthetic_file("open.m8t", Theme::SIZE);
+        assert_eq!(open(&theme.0).unwrap().kind(), M8FileKind::Theme);
+
+        let scale = synthetic_file("open.m8n", Scale::SIZE);
+        assert_eq!(open(&scale.0).unwrap().kind(), M8FileKind::Scale);
+    }
+
+    #[test]
+    fn open_rejects_unknown_files() {
+        let file = synthetic_file("open.bin", 4);
+        let err = open(&file.0).err().expect("Unknown file opened");
+        assert_eq!(err.kind(), ParseErrorKind::UnknownFileType);
+    }
+}
@@ -4,22 +4,42 @@ use crate::version::*;
 use crate::writer::Writer;
 use crate::CommandPack;
 use array_concat::*;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::OnceLock;
+
+type CommandIndex = HashMap<&'static str, u8>;
 
 #[derive(Copy, Clone)]
 pub struct FxCommands {
     pub commands: &'static [&'static str],
+
+    /// Lazily built reverse lookup (name -> command id)
+    index: &'static OnceLock<CommandIndex>,
 }
 
 impl FxCommands {
-    pub fn find_indices(&self, to_find: &[&str]) -> Vec<u8> {
-        let mut out = vec![];
-
-        for (i, cmd) in self.commands.iter().enumerate() {
-            if to_find.contains(cmd) {
-                out.push(i as u8)
+    /// Mapping from command name to command id, built on first use
+    /// and shared by every FxCommands of the same version.
+    pub fn index_map(&self) -> &CommandIndex {
+        self.index.get_or_init(|| {
+            let mut map = HashMap::with_capacity(self.commands.len());
+            for (i, cmd) in self.commands.iter().enumerate() {
+                map.entry(*cmd).or_insert(i as u8);
             }
-        }
+            map
+        })
+    }
+
+    pub fn find_indices(&self, to_find: &[&str]) -> Vec<u8> {
+        let index = self.index_map();
+        let mut out: Vec<u8> = to_find
+            .iter()
+            .filter_map(|cmd| index.get(cmd).copied())
+            .collect();
 
+        out.sort_unstable();
+        out.dedup();
         out
     }
 
@@ -163,6 +183,8 @@ const FX_MIXER_COMMAND_V2 : [&'static str; 36] = [
 const COMMANDS_V2: [&'static str; concat_arrays_size!(SEQ_COMMAND_V2, FX_MIXER_COMMAND_V2)] =
     concat_arrays!(SEQ_COMMAND_V2, FX_MIXER_COMMAND_V2);
 
+static COMMANDS_V2_INDEX: OnceLock<CommandIndex> = OnceLock::new();
+
 //////////////////////////////////////////
 // MARK: V3 commands
 //////////////////////////////////////////
@@ -241,6 +263,8 @@ const FX_MIXER_COMMAND_V3 : [&'static str; 36] = [
 const COMMANDS_V3: [&'static str; concat_arrays_size!(SEQ_COMMAND_V3, FX_MIXER_COMMAND_V3)] =
     concat_arrays!(SEQ_COMMAND_V3, FX_MIXER_COMMAND_V3);
 
+static COMMANDS_V3_INDEX: OnceLock<CommandIndex> = OnceLock::new();
+
 //////////////////////////////////////////
 // MARK: V4 commands
 //////////////////////////////////////////
@@ -298,9 +322,31 @@ const FX_MIXER_COMMAND_V4 : [&'static str; 45] = [
 const COMMANDS_V4: [&'static str; concat_arrays_size!(SEQ_COMMAND_V3, FX_MIXER_COMMAND_V4)] =
     concat_arrays!(SEQ_COMMAND_V3, FX_MIXER_COMMAND_V4);
 
+static COMMANDS_V4_INDEX: OnceLock<CommandIndex> = OnceLock::new();
+
 impl FX {
     pub const V4_SIZE: usize = 2;
 
+    /// First command byte of the instrument specific commands, the
+    /// global sequencer and mixer commands being below it.
+    pub const INSTRUMENT_COMMAND_OFFSET: u8 = CommandPack::INSTRUMENT_COMMAND_OFFSET as u8;
+
+    /// Tell if the command is instrument specific, the empty command
+    /// (0xFF) being excluded.
+    pub fn is_instrument_command(cmd: u8) -> bool {
+        cmd >= FX::INSTRUMENT_COMMAND_OFFSET && cmd != 0xFF
+    }
+
+    /// Index of the command in the instrument command list, None for
+    /// a global or empty command.
+    pub fn instrument_command_index(cmd: u8) -> Option<u8> {
+        if FX::is_instrument_command(cmd) {
+            Some(cmd - FX::INSTRUMENT_COMMAND_OFFSET)
+        } else {
+            None
+        }
+    }
+
     pub(crate) fn from_reader(reader: &mut Reader) -> M8Result<Self> {
         Ok(Self {
             command: reader.read(),
@@ -317,13 +363,77 @@ impl FX {
         self.command == 0xFF
     }
 
+    /// Check the value against the documented range of the command,
+    /// instrument commands being resolved with `pack`. Commands without
+    /// known range are never flagged.
+    pub fn validate(&self, fx: FxCommands, pack: CommandPack) -> Option<ValidationWarning> {
+        let (command, meta) = match fx.try_render(self.command) {
+            Some(name) => (name, find_meta(&COMMAND_META, name)?),
+            None if pack.accepts(self.command) => {
+                let name = pack.try_render(self.command)?;
+                (name, find_meta(&INSTRUMENT_COMMAND_META, name)?)
+            }
+            None => return None,
+        };
+
+        if self.value < meta.min || self.value > meta.max {
+            Some(ValidationWarning {
+                command,
+                value: self.value,
+                min: meta.min,
+                max: meta.max,
+            })
+        } else {
+            None
+        }
+    }
+
     pub fn print(&self, fx: FxCommands, pack: CommandPack) -> String {
+        let mut out = String::with_capacity(5);
+        // writing to a String never fails
+        let _ = self.write_fmt(&mut out, fx, pack);
+        out
+    }
+
+    /// Same rendering as [`FX::print`], written straight into `out`
+    /// without intermediate allocation.
+    pub fn write_fmt(
+        &self,
+        out: &mut impl fmt::Write,
+        fx: FxCommands,
+        pack: CommandPack,
+    ) -> fmt::Result {
         if self.is_empty() {
-            format!("---  ")
-        } else {
-            let c = self.format_command(fx, pack);
-            format!("{}{:02x}", c, self.value)
+            return out.write_str("---  ");
         }
+
+        match fx.try_render(self.command) {
+            Some(s) => out.write_str(s)?,
+            None if pack.accepts(self.command) => match pack.try_render(self.command) {
+                Some(v) => out.write_str(v)?,
+                None => write!(out, "I{:02X}", self.command - FX::INSTRUMENT_COMMAND_OFFSET)?,
+            },
+            None => write!(out, "?{:02x}", self.command)?,
+        }
+        write!(out, "{:02x}", self.value)
+    }
+
+    /// Same rendering as [`FX::print`], but erroring on commands unknown
+    /// to the version and instrument instead of rendering them as
+    /// `I{:02X}` or `?{:02x}` placeholders.
+    pub fn format_command_strict(&self, fx: FxCommands, pack: CommandPack) -> M8Result<String> {
+        let known = self.is_empty()
+            || fx.try_render(self.command).is_some()
+            || pack.try_render(self.command).is_some();
+
+        if !known {
+            return Err(ParseError::new(
+                ParseErrorKind::UnknownCommand,
+                format!("Command {:02x} has no name", self.command),
+            ));
+        }
+
+        Ok(self.print(fx, pack))
     }
 
     /// Retrieve command names for a given version
@@ -331,31 +441,367 @@ impl FX {
         if ver.at_least(4, 0) {
             FxCommands {
                 commands: &COMMANDS_V4,
+                index: &COMMANDS_V4_INDEX,
             }
         } else if ver.at_least(3, 0) {
             FxCommands {
                 commands: &COMMANDS_V3,
+                index: &COMMANDS_V3_INDEX,
             }
         } else {
             FxCommands {
                 commands: &COMMANDS_V2,
+                index: &COMMANDS_V2_INDEX,
             }
         }
     }
+}
 
-    fn format_command(&self, fx: FxCommands, instr: CommandPack) -> String {
-        match fx.try_render(self.command) {
-            Some(s) => String::from(s),
-            None => {
-                if instr.accepts(self.command) {
-                    match instr.try_render(self.command) {
-                        Some(v) => String::from(v),
-                        None => format!("I{:02X}", self.command - 0x80),
-                    }
-                } else {
-                    format!("?{:02x}", self.command)
-                }
-            }
+/// Sequencer commands (everything but the mixer and FX ones) of the
+/// given firmware version, in command number order.
+pub fn seq_commands(ver: Version) -> &'static [&'static str] {
+    if ver.at_least(3, 0) {
+        &SEQ_COMMAND_V3
+    } else {
+        &SEQ_COMMAND_V2
+    }
+}
+
+/// Tell if a sequencer or mixer command exists in the given firmware
+/// version.
+pub fn command_supported(mnemonic: &str, ver: Version) -> bool {
+    FX::fx_command_names(ver).index_map().contains_key(mnemonic)
+}
+
+/// Commands renamed between firmware versions, (old name, new name)
+#[rustfmt::skip] // Keep constants with important order vertical for maintenance
+const COMMAND_RENAMES : [(&str, &str); 6] = [
+    ("RAN", "RND"),
+    ("PSN", "PBN"),
+    ("VCD", "VDE"),
+    ("DJF", "DJC"),
+    ("IVO", "VIN"),
+    ("IV2", "VI2"),
+];
+
+/// Convert a sequencer or mixer FX from the command numbering of a
+/// firmware version to the one of another, following the command by
+/// name (and renames). Instrument specific and empty FX are kept as
+/// is, a command without equivalent in the target version becomes
+/// an empty FX.
+pub fn migrate_fx(fx: FX, from: Version, to: Version) -> FX {
+    let Some(name) = FX::fx_command_names(from).try_render(fx.command) else {
+        return fx;
+    };
+
+    let target_commands = FX::fx_command_names(to);
+    let target = target_commands.index_map();
+    let renamed = COMMAND_RENAMES.iter().find_map(|&(old, new)| {
+        if old == name {
+            Some(new)
+        } else if new == name {
+            Some(old)
+        } else {
+            None
+        }
+    });
+
+    let command = target
+        .get(name)
+        .or_else(|| renamed.and_then(|n| target.get(n)))
+        .copied();
+
+    match command {
+        Some(command) => FX {
+            command,
+            value: fx.value,
+        },
+        None => FX::default(),
+    }
+}
+
+//////////////////////////////////////////
+// MARK: Command metadata
+//////////////////////////////////////////
+
+/// How the value of a command should be displayed
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum CommandUnit {
+    /// Plain hexadecimal value
+    Raw,
+    /// Tempo in beats per minute
+    Bpm,
+    /// Duration in ticks
+    Ticks,
+    /// Pitch offset in semitones
+    Semitones,
+    /// Groove number
+    Groove,
+    /// Table number
+    Table,
+    /// Scale number
+    Scale,
+}
+
+/// Range and display information for the value of a FX command
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct CommandMeta {
+    pub min: u8,
+    pub max: u8,
+
+    /// Value is to be interpreted as a two's complement signed byte
+    pub signed: bool,
+
+    pub unit: CommandUnit,
+}
+
+impl CommandMeta {
+    const fn new(min: u8, max: u8, signed: bool, unit: CommandUnit) -> Self {
+        Self {
+            min,
+            max,
+            signed,
+            unit,
+        }
+    }
+}
+
+#[rustfmt::skip] // Keep constants with important order vertical for maintenance
+const COMMAND_META: [(&str, CommandMeta); 10] = [
+    ("DEL", CommandMeta::new(0x00, 0xFF, false, CommandUnit::Ticks)),
+    ("GRV", CommandMeta::new(0x00, 0x1F, false, CommandUnit::Groove)),
+    ("KIL", CommandMeta::new(0x00, 0xFF, false, CommandUnit::Ticks)),
+    ("PSL", CommandMeta::new(0x00, 0xFF, false, CommandUnit::Ticks)),
+    ("SCA", CommandMeta::new(0x00, 0x0F, false, CommandUnit::Scale)),
+    ("TBL", CommandMeta::new(0x00, 0xFF, false, CommandUnit::Table)),
+    ("TIC", CommandMeta::new(0x01, 0xFF, false, CommandUnit::Ticks)),
+    ("TPO", CommandMeta::new(0x28, 0xFF, false, CommandUnit::Bpm)),
+    ("TSP", CommandMeta::new(0x00, 0xFF, true, CommandUnit::Semitones)),
+    ("VMV", CommandMeta::new(0x00, 0xFF, false, CommandUnit::Raw)),
+];
+
+/// Instrument commands with a restricted range, other instrument
+/// commands accepting any value.
+#[rustfmt::skip] // Keep constants with important order vertical for maintenance
+const INSTRUMENT_COMMAND_META: [(&str, CommandMeta); 1] = [
+    ("ALG", CommandMeta::new(0x00, crate::FmAlgo::MAX.0, false, CommandUnit::Raw)),
+];
+
+fn find_meta(metas: &[(&str, CommandMeta)], name: &str) -> Option<CommandMeta> {
+    metas
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, meta)| *meta)
+}
+
+/// FX value outside of the range accepted by its command
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct ValidationWarning {
+    /// Command mnemonic ("TPO", "ALG"...)
+    pub command: &'static str,
+    pub value: u8,
+    pub min: u8,
+    pub max: u8,
+}
+
+/// Value range and display format of a sequencer or mixer command,
+/// None for unknown (or instrument specific) commands, whose value
+/// should be edited as raw hexadecimal.
+pub fn command_meta(cmd: u8, ver: Version) -> Option<CommandMeta> {
+    let name = FX::fx_command_names(ver).try_render(cmd)?;
+    find_meta(&COMMAND_META, name)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fx::*;
+
+    #[test]
+    fn seq_command_lists_per_version() {
+        let v2 = seq_commands(Version {
+            major: 2,
+            minor: 7,
+            patch: 0,
+        });
+        let v3 = seq_commands(Version {
+            major: 3,
+            minor: 0,
+            patch: 0,
+        });
+
+        assert_eq!(v2.len(), 23);
+        assert_eq!(v3.len(), 27);
+        assert!(v2.contains(&"RAN") && !v3.contains(&"RAN"));
+        assert!(v3.contains(&"RND") && v3.contains(&"TBX"));
+        assert_eq!(seq_commands(Version::default()), v3);
+    }
+
+    #[test]
+    fn index_map_agrees_with_find_indices() {
+        let cmds = FX::fx_command_names(Version::default());
+        let index = cmds.index_map();
+
+        assert_eq!(index.len(), COMMANDS_V4.len());
+        for name in COMMANDS_V4.iter() {
+            assert_eq!(cmds.find_indices(&[name]), vec![index[name]]);
+        }
+    }
+
+    #[test]
+    fn tempo_and_delay_meta() {
+        let ver = Version::default();
+        let cmds = FX::fx_command_names(ver);
+
+        let tpo = cmds.find_indices(&["TPO"])[0];
+        let meta = command_meta(tpo, ver).unwrap();
+        assert_eq!((meta.min, meta.max), (0x28, 0xFF));
+        assert_eq!(meta.unit, CommandUnit::Bpm);
+        assert!(!meta.signed);
+
+        let del = cmds.find_indices(&["DEL"])[0];
+        assert_eq!(command_meta(del, ver).unwrap().unit, CommandUnit::Ticks);
+
+        assert_eq!(command_meta(0x80, ver), None);
+    }
+
+    #[test]
+    fn write_fmt_matches_print() {
+        let ver = Version::default();
+        let cmds = FX::fx_command_names(ver);
+        let pack =
+            crate::Instrument::FMSynth(crate::FMSynth::default_for(ver)).instr_command_text(ver);
+
+        let mut grid = String::new();
+        let mut expected = String::new();
+        for command in [0x00, 0x03, 0x70, 0x80, 0x83, 0xA0, 0xFE, 0xFF] {
+            let fx = FX {
+                command,
+                value: 0x2A,
+            };
+            fx.write_fmt(&mut grid, cmds, pack).unwrap();
+            expected.push_str(&fx.print(cmds, pack));
         }
+
+        assert_eq!(grid, expected);
+        assert!(grid.starts_with("ARP2a"));
+        assert!(grid.ends_with("---  "));
+    }
+
+    #[test]
+    fn strict_format_rejects_unknown_command() {
+        let ver = Version::default();
+        let cmds = FX::fx_command_names(ver);
+        let pack =
+            crate::Instrument::FMSynth(crate::FMSynth::default_for(ver)).instr_command_text(ver);
+
+        let unknown = FX {
+            command: 0xFE,
+            value: 0x2A,
+        };
+        assert_eq!(unknown.print(cmds, pack), "?fe2a");
+        let err = unknown.format_command_strict(cmds, pack).unwrap_err();
+        assert_eq!(err.kind(), ParseErrorKind::UnknownCommand);
+
+        let arp = FX {
+            command: 0x00,
+            value: 0x2A,
+        };
+        assert_eq!(arp.format_command_strict(cmds, pack).unwrap(), "ARP2a");
+        assert_eq!(
+            FX::default().format_command_strict(cmds, pack).unwrap(),
+            "---  "
+        );
+    }
+
+    #[test]
+    fn out_of_range_values_warn() {
+        let ver = Version::default();
+        let cmds = FX::fx_command_names(ver);
+        let pack =
+            crate::Instrument::FMSynth(crate::FMSynth::default_for(ver)).instr_command_text(ver);
+
+        let alg = pack.find_command("ALG").unwrap();
+        let fx = |command, value| FX { command, value };
+        assert_eq!(fx(alg, 0x0B).validate(cmds, pack), None);
+        assert_eq!(
+            fx(alg, 0x0C).validate(cmds, pack),
+            Some(ValidationWarning {
+                command: "ALG",
+                value: 0x0C,
+                min: 0x00,
+                max: 0x0B,
+            })
+        );
+
+        let tpo = cmds.find_indices(&["TPO"])[0];
+        assert_eq!(fx(tpo, 0x10).validate(cmds, pack).unwrap().command, "TPO");
+        assert_eq!(fx(tpo, 0x78).validate(cmds, pack), None);
+        assert_eq!(FX::default().validate(cmds, pack), None);
+    }
+
+    #[test]
+    fn instrument_command_boundary() {
+        assert!(!FX::is_instrument_command(0x7F));
+        assert!(FX::is_instrument_command(0x80));
+        assert_eq!(FX::instrument_command_index(0x7F), None);
+        assert_eq!(FX::instrument_command_index(0x80), Some(0));
+        assert_eq!(FX::instrument_command_index(0x92), Some(0x12));
+        assert_eq!(FX::instrument_command_index(0xFF), None);
+    }
+
+    #[test]
+    fn command_support_by_version() {
+        let v2 = Version {
+            major: 2,
+            minor: 7,
+            patch: 0,
+        };
+        let v3 = Version {
+            major: 3,
+            minor: 0,
+            patch: 0,
+        };
+
+        assert!(command_supported("RAN", v2));
+        assert!(!command_supported("RAN", v3));
+        assert!(!command_supported("RAN", Version::default()));
+
+        assert!(!command_supported("RMX", v2));
+        assert!(command_supported("RMX", v3));
+        assert!(command_supported("RMX", Version::default()));
+    }
+
+    #[test]
+    fn migrate_ran_to_rnd() {
+        let v2 = Version {
+            major: 2,
+            minor: 7,
+            patch: 0,
+        };
+        let v3 = Version {
+            major: 3,
+            minor: 0,
+            patch: 0,
+        };
+        let ran = FX {
+            command: FX::fx_command_names(v2).find_indices(&["RAN"])[0],
+            value: 0x42,
+        };
+        let rnd = FX::fx_command_names(v3).find_indices(&["RND"])[0];
+
+        assert_eq!(
+            migrate_fx(ran, v2, v3),
+            FX {
+                command: rnd,
+                value: 0x42
+            }
+        );
+        assert_eq!(migrate_fx(migrate_fx(ran, v2, v3), v3, v2), ran);
+
+        let v3_only = FX {
+            command: FX::fx_command_names(v3).find_indices(&["RMX"])[0],
+            value: 0,
+        };
+        assert!(migrate_fx(v3_only, v3, v2).is_empty());
     }
 }
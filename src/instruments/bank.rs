@@ -0,0 +1,359 @@
+use std::path::Path;
+
+use crate::reader::*;
+use crate::version::Version;
+use crate::writer::Writer;
+
+use super::Instrument;
+
+/// The 128 instrument slots of a song, all sharing the version of
+/// the song. Empty slots are `None`.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Bank {
+    pub version: Version,
+    slots: [Option<Instrument>; Bank::SLOT_COUNT],
+}
+
+impl Bank {
+    pub const SLOT_COUNT: usize = 128;
+
+    /// Bank with every slot empty
+    pub fn empty(version: Version) -> Self {
+        Self {
+            version,
+            slots: std::array::from_fn(|_| None),
+        }
+    }
+
+    /// Read the consecutive instruments of a bank, starting at the
+    /// current position of the reader (e.g. the instrument offset of a
+    /// song file).
+    pub fn read(reader: &mut Reader, version: Version) -> M8Result<Self> {
+        let mut bank = Bank::empty(version);
+        for (i, slot) in bank.slots.iter_mut().enumerate() {
+            let instr = Instrument::from_reader(reader, i as u8, version)?;
+            if !instr.is_empty() {
+                *slot = Some(instr);
+            }
+        }
+
+        Ok(bank)
+    }
+
+    /// Write every slot at the bank stride, from the current position
+    /// of the writer. Empty slots only get their kind byte rewritten.
+    pub fn write(&self, w: &mut Writer) {
+        let stride = Instrument::bank_stride(self.version);
+        for slot in &self.slots {
+            let pos = w.pos();
+            slot.as_ref()
+                .unwrap_or(&Instrument::None)
+                .write(self.version, w);
+            w.seek(pos + stride);
+        }
+    }
+
+    /// Serialize the bank in a new buffer, preallocated to the size of
+    /// its slots. Empty slots are fully written, as there are no
+    /// existing bytes to keep.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let stride = Instrument::bank_stride(self.version);
+        let mut w = Writer::with_capacity(Bank::SLOT_COUNT * stride);
+        for (i, slot) in self.slots.iter().enumerate() {
+            w.seek(i * stride);
+            match slot {
+                Some(instr) => instr.write(self.version, &mut w),
+                None => Instrument::write_empty_slot(&mut w),
+            }
+        }
+
+        w.finish()
+    }
+
+    /// Non empty slots, with their index
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &Instrument)> {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter_map(|(i, slot)| slot.as_ref().map(|instr| (i, instr)))
+    }
+
+    /// Instrument in the slot, None if empty or out of the bank
+    pub fn get(&self, index: usize) -> Option<&Instrument> {
+        self.slots.get(index)?.as_ref()
+    }
+
+    /// Replace the content of a slot, `None` (or `Instrument::None`)
+    /// clearing it. Indices out of the bank are ignored.
+    pub fn set(&mut self, index: usize, instr: Option<Instrument>) {
+        if let Some(slot) = self.slots.get_mut(index) {
+            *slot = instr.filter(|i| !i.is_empty());
+        }
+    }
+}
+
+/// Replace the sample path of every sampler of the bank by the result
+/// of `f`, e.g. to move the samples of a project to another directory.
+/// Paths longer than the sample path field get truncated.
+pub fn rewrite_sample_paths(bank: &mut Bank, mut f: impl FnMut(&str) -> String) {
+    for slot in bank.slots.iter_mut().flatten() {
+        if let Instrument::Sampler(smp) = slot {
+            let path = f(&smp.sample_path);
+            smp.set_sample_path(&path);
+        }
+    }
+}
+
+/// Sorted list of the sample paths used by the samplers of the bank,
+/// without empty paths. Paths differing only by case are listed once.
+pub fn sample_manifest(bank: &Bank) -> Vec<String> {
+    let mut paths: Vec<String> = bank
+        .iter()
+        .filter_map(|(_, instr)| match instr {
+            Instrument::Sampler(smp) if !smp.sample_path.is_empty() => {
+                Some(smp.sample_path.clone())
+            }
+            _ => None,
+        })
+        .collect();
+
+    paths.sort_by_key(|p| p.to_ascii_lowercase());
+    paths.dedup_by(|a, b| a.eq_ignore_ascii_case(b));
+    paths
+}
+
+fn io_error(path: &Path, e: std::io::Error) -> ParseError {
+    ParseError::new(
+        ParseErrorKind::Io,
+        format!("Could not access {}: {}", path.display(), e),
+    )
+}
+
+/// File name of an instrument exported by [`write_dir`]: the slot in
+/// hexadecimal, as displayed by the M8, then the instrument name with
+/// the characters unsafe for a file system replaced.
+fn slot_file_name(slot: usize, instr: &Instrument) -> String {
+    let name: String = instr
+        .name()
+        .unwrap_or("")
+        .trim()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    if name.is_empty() {
+        format!("{:02X}.m8i", slot)
+    } else {
+        format!("{:02X}_{}.m8i", slot, name)
+    }
+}
+
+/// Write every non empty slot of the bank to its own instrument file
+/// (.m8i) in `dir`, named after its slot and instrument name. A bank
+/// holds no EQ, so none is embedded in the files.
+pub fn write_dir(bank: &Bank, dir: &Path, ver: Version) -> M8Result<()> {
+    std::fs::create_dir_all(dir).map_err(|e| io_error(dir, e))?;
+
+    for (slot, instr) in bank.iter() {
+        let path = dir.join(slot_file_name(slot, instr));
+        std::fs::write(&path, instr.write_m8i(ver, None)).map_err(|e| io_error(&path, e))?;
+    }
+
+    Ok(())
+}
+
+/// Load the instrument files of `dir` in a bank, the slot of each
+/// instrument being given by the two hexadecimal digits starting its
+/// file name (as written by [`write_dir`]). Other files are ignored.
+/// Every instrument file must be of version `ver`, as a bank holds
+/// instruments of a single layout.
+pub fn read_dir(dir: &Path, ver: Version) -> M8Result<Bank> {
+    let mut bank = Bank::empty(ver);
+
+    for entry in std::fs::read_dir(dir).map_err(|e| io_error(dir, e))? {
+        let path = entry.map_err(|e| io_error(dir, e))?.path();
+        let is_m8i = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| e.eq_ignore_ascii_case("m8i"));
+        if !is_m8i {
+            continue;
+        }
+
+        let slot = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .and_then(|n| n.get(..2))
+            .and_then(|n| u8::from_str_radix(n, 16).ok())
+            .filter(|&n| (n as usize) < Bank::SLOT_COUNT);
+        let Some(slot) = slot else {
+            continue;
+        };
+
+        let bytes = std::fs::read(&path).map_err(|e| io_error(&path, e))?;
+        let mut reader = Reader::new(bytes);
+        let file_ver = Version::from_reader(&mut reader)?;
+        if file_ver != ver {
+            return Err(ParseError::new(
+                ParseErrorKind::UnsupportedVersion,
+                format!(
+                    "{} is in version {}, expected {}",
+                    path.display(),
+                    file_ver,
+                    ver
+                ),
+            ));
+        }
+
+        let instr = Instrument::from_reader(&mut reader, slot, file_ver)?;
+        bank.set(slot as usize, Some(instr));
+    }
+
+    Ok(bank)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::slot_file_name;
+    use crate::instruments::*;
+    use crate::writer::Writer;
+    use crate::{Version, V4_OFFSETS};
+
+    #[test]
+    fn clear_slot_and_write_back() {
+        let bytes =
+            std::fs::read("./examples/songs/Bundle/FDUB3.m8s").expect("Could not open FDUB3");
+        let version = Version::from_reader(&mut Reader::new(bytes.clone())).unwrap();
+
+        let mut reader = Reader::new(bytes.clone());
+        reader.set_pos(V4_OFFSETS.instruments);
+        let mut bank = Bank::read(&mut reader, version).unwrap();
+
+        let used: Vec<usize> = bank.iter().map(|(i, _)| i).collect();
+        assert!(used.len() >= 2);
+        let cleared = used[0];
+        let kept = bank.get(used[1]).cloned();
+        bank.set(cleared, None);
+
+        let mut w = Writer::new(bytes);
+        w.seek(V4_OFFSETS.instruments);
+        bank.write(&mut w);
+
+        let mut reader = Reader::new(w.finish());
+        reader.set_pos(V4_OFFSETS.instruments);
+        let reread = Bank::read(&mut reader, version).unwrap();
+        assert_eq!(reread.get(cleared), None);
+        assert_eq!(reread.get(used[1]).cloned(), kept);
+        assert_eq!(reread.iter().count(), used.len() - 1);
+    }
+
+    #[test]
+    fn fresh_bank_bytes_cover_every_slot() {
+        let ver = Version::default();
+        let mut bank = Bank::empty(ver);
+        bank.set(1, Some(Instrument::WavSynth(WavSynth::default_for(ver))));
+
+        let bytes = bank.to_bytes();
+        let stride = Instrument::bank_stride(ver);
+        assert_eq!(bytes.len(), Bank::SLOT_COUNT * stride);
+
+        let reread = Bank::read(&mut Reader::new(bytes), ver).unwrap();
+        let slots: Vec<usize> = reread.iter().map(|(i, _)| i).collect();
+        assert_eq!(slots, [1]);
+        assert!(matches!(reread.get(1), Some(Instrument::WavSynth(_))));
+    }
+
+    #[test]
+    fn sample_path_prefix_rewrite() {
+        let ver = Version::default();
+        let sampler = |path: &str| {
+            let mut smp = Sampler::default_for(ver);
+            smp.sample_path = path.to_string();
+            Instrument::Sampler(smp)
+        };
+
+        let mut bank = Bank::empty(ver);
+        bank.set(0, Some(sampler("/Samples/old/kick.wav")));
+        bank.set(1, Some(Instrument::WavSynth(WavSynth::default_for(ver))));
+        bank.set(4, Some(sampler("/Samples/old/snare.wav")));
+
+        rewrite_sample_paths(&mut bank, |p| {
+            p.replacen("/Samples/old/", "/Samples/new/", 1)
+        });
+
+        let paths: Vec<&str> = bank
+            .iter()
+            .filter_map(|(_, i)| match i {
+                Instrument::Sampler(s) => Some(s.sample_path.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(paths, ["/Samples/new/kick.wav", "/Samples/new/snare.wav"]);
+
+        rewrite_sample_paths(&mut bank, |p| p.repeat(20));
+        match bank.get(0) {
+            Some(Instrument::Sampler(s)) => {
+                assert_eq!(s.sample_path.len(), Sampler::SAMPLE_PATH_LENGTH)
+            }
+            other => panic!("Expected a sampler, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn manifest_lists_shared_path_once() {
+        let ver = Version::default();
+        let sampler = |path: &str| {
+            let mut smp = Sampler::default_for(ver);
+            smp.sample_path = path.to_string();
+            Instrument::Sampler(smp)
+        };
+
+        let mut bank = Bank::empty(ver);
+        bank.set(0, Some(sampler("/Samples/snare.wav")));
+        bank.set(1, Some(sampler("/Samples/Kick.wav")));
+        bank.set(2, Some(sampler("/Samples/kick.wav")));
+        bank.set(3, Some(sampler("")));
+
+        assert_eq!(
+            sample_manifest(&bank),
+            ["/Samples/Kick.wav", "/Samples/snare.wav"]
+        );
+    }
+
+    #[test]
+    fn bank_round_trips_through_directory() {
+        let bytes =
+            std::fs::read("./examples/songs/Bundle/FDUB3.m8s").expect("Could not open FDUB3");
+        let version = Version::from_reader(&mut Reader::new(bytes.clone())).unwrap();
+        let mut reader = Reader::new(bytes);
+        reader.set_pos(V4_OFFSETS.instruments);
+        let bank = Bank::read(&mut reader, version).unwrap();
+
+        let dir = std::env::temp_dir().join(format!("m8-bank-{}", std::process::id()));
+        write_dir(&bank, &dir, version).unwrap();
+        let (slot, instr) = bank.iter().next().unwrap();
+        let upper = dir.join(format!("{:02X}.M8I", slot));
+        std::fs::rename(dir.join(slot_file_name(slot, instr)), &upper).unwrap();
+
+        let reread = read_dir(&dir, version);
+        let other_version = Version {
+            major: 3,
+            minor: 0,
+            patch: 0,
+        };
+        let mismatch = read_dir(&dir, other_version);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(reread.unwrap(), bank);
+        assert_eq!(
+            mismatch.err().map(|e| e.kind()),
+            Some(ParseErrorKind::UnsupportedVersion)
+        );
+    }
+}
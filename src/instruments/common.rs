@@ -1,11 +1,37 @@
 use std::fmt;
 
+use super::lookup;
 use super::modulator::*;
 use crate::reader::*;
 use crate::writer::Writer;
 use crate::Version;
 use arr_macro::arr;
 
+/// How the transpose flag and EQ number share the byte following the
+/// instrument name, depending on the firmware version.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum TranspEqLayout {
+    /// Before 4.0 there is no EQ, only the transpose flag in bit 0
+    TransposeOnly,
+
+    /// From 4.0, transpose flag in bit 0 and EQ number in the 7 high bits
+    Packed,
+
+    /// From 4.1, only the transpose flag in bit 0: the EQ number has
+    /// its own byte, right before the modulators.
+    SeparateEq,
+}
+
+impl TranspEqLayout {
+    /// Number of bits of the byte storing the EQ number
+    pub fn eq_bits(self) -> u32 {
+        match self {
+            TranspEqLayout::Packed => 7,
+            TranspEqLayout::TransposeOnly | TranspEqLayout::SeparateEq => 0,
+        }
+    }
+}
+
 /// Type storing transpose field and eq number
 #[derive(PartialEq, Copy, Clone, Default, Debug)]
 pub struct TranspEq {
@@ -14,28 +40,47 @@ pub struct TranspEq {
 }
 
 impl TranspEq {
-    pub fn from(ver: Version, transpose: bool, eq: u8) -> TranspEq {
+    /// Layout of the transpose/EQ byte for the firmware version
+    pub fn layout(ver: Version) -> TranspEqLayout {
         if ver.at_least(4, 1) {
-            Self {
+            TranspEqLayout::SeparateEq
+        } else if ver.at_least(4, 0) {
+            TranspEqLayout::Packed
+        } else {
+            TranspEqLayout::TransposeOnly
+        }
+    }
+
+    /// Value to write for the version, the EQ number being dropped
+    /// when the layout has no room for it.
+    pub fn from(ver: Version, transpose: bool, eq: u8) -> TranspEq {
+        match TranspEq::layout(ver) {
+            TranspEqLayout::Packed => Self { transpose, eq },
+            _ => Self {
                 transpose,
                 eq: 0x00,
-            }
-        } else {
-            Self { transpose, eq }
+            },
         }
     }
 
+    /// Decode the byte for the version. Without EQ (before 4.0) the
+    /// EQ number is 0xFF, as for an instrument without EQ, and from
+    /// 4.1 it is 0 as it is read from its own byte.
     pub fn from_version(ver: Version, value: u8) -> Self {
-        if ver.at_least(4, 1) {
-            Self {
-                transpose: (value & 1) != 0,
-                eq: 0x00,
-            }
-        } else {
-            Self {
-                transpose: (value & 1) != 0,
+        let transpose = (value & 1) != 0;
+        match TranspEq::layout(ver) {
+            TranspEqLayout::TransposeOnly => Self {
+                transpose,
+                eq: 0xFF,
+            },
+            TranspEqLayout::Packed => Self {
+                transpose,
                 eq: value >> 1,
-            }
+            },
+            TranspEqLayout::SeparateEq => Self {
+                transpose,
+                eq: 0x00,
+            },
         }
     }
 }
@@ -64,7 +109,7 @@ pub struct LimitType(pub u8);
 
 impl fmt::Debug for LimitType {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.write_str(LIMIT_TYPE[self.0 as usize])
+        f.write_str(self.str())
     }
 }
 
@@ -75,7 +120,10 @@ impl TryFrom<u8> for LimitType {
         if (value as usize) < LIMIT_TYPE.len() {
             Ok(LimitType(value))
         } else {
-            Err(ParseError(format!("Invalid fm wave {}", value)))
+            Err(ParseError::new(
+                ParseErrorKind::InvalidValue(value),
+                format!("Invalid limit type {}", value),
+            ))
         }
     }
 }
@@ -87,10 +135,19 @@ impl LimitType {
     }
 
     pub fn str(self) -> &'static str {
-        LIMIT_TYPE[self.id() as usize]
+        lookup(&LIMIT_TYPE, self.id())
     }
 }
 
+/// Mixer send of an instrument, as found on the instrument page
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum MixerSend {
+    Dry,
+    Chorus,
+    Delay,
+    Reverb,
+}
+
 #[derive(PartialEq, Debug, Clone)]
 pub struct SynthParams {
     pub volume: u8,
@@ -112,6 +169,12 @@ pub struct SynthParams {
 
     pub associated_eq: u8,
 
+    /// Bytes found between the mixer parameters and the modulators,
+    /// kept to write them back unchanged (the EQ byte of 4.1 files
+    /// being stored as 0). Empty when they are all zero or when the
+    /// instrument was not read from a file, zeroes being written then.
+    pub mod_padding: Vec<u8>,
+
     pub mods: [Mod; SynthParams::MODULATOR_COUNT],
 }
 
@@ -134,6 +197,133 @@ impl SynthParams {
         self.associated_eq = eq
     }
 
+    /// Filter state as on the device filter page: the filter name from
+    /// `filter_types` (the list of the owning synth) followed by the
+    /// cutoff and resonance in hex, like "LOWPASS 40 10".
+    pub fn describe_filter(&self, filter_types: &[&'static str]) -> String {
+        format!(
+            "{} {:02X} {:02X}",
+            super::lookup(filter_types, self.filter_type),
+            self.filter_cutoff,
+            self.filter_res
+        )
+    }
+
+    /// Set the pan from a signed value, 0 being centered (0x80),
+    /// clamped to the -127..127 range of the device.
+    pub fn set_pan(&mut self, signed: i8) {
+        self.mixer_pan = (0x80 + i16::from(signed.max(-127))) as u8
+    }
+
+    /// Pan as a signed value, 0 being centered.
+    pub fn pan(&self) -> i8 {
+        (i16::from(self.mixer_pan) - 0x80).max(-127) as i8
+    }
+
+    pub fn set_send(&mut self, send: MixerSend, level: u8) {
+        match send {
+            MixerSend::Dry => self.mixer_dry = level,
+            MixerSend::Chorus => self.mixer_chorus = level,
+            MixerSend::Delay => self.mixer_delay = level,
+            MixerSend::Reverb => self.mixer_reverb = level,
+        }
+    }
+
+    /// Slots of the modulators pointing at the given destination index
+    /// (as listed by the instrument destination names).
+    pub fn mods_targeting(&self, dest_index: u8) -> Vec<usize> {
+        self.mods
+            .iter()
+            .enumerate()
+            .filter(|(_, m)| m.dest() == dest_index)
+            .map(|(slot, _)| slot)
+            .collect()
+    }
+
+    /// Turn a modulator slot off, as an AHD envelope without
+    /// destination and all its bytes, reserved one included, at 0.
+    pub fn clear_mod(&mut self, slot: usize) -> M8Result<()> {
+        match self.mods.get_mut(slot) {
+            Some(m) => {
+                *m = AHDEnv::default().to_mod();
+                Ok(())
+            }
+            None => Err(ParseError::new(
+                ParseErrorKind::InvalidValue(slot.min(0xFF) as u8),
+                format!("Invalid modulator slot {}", slot),
+            )),
+        }
+    }
+
+    /// Modulators of a freshly created instrument, two AHD envelopes
+    /// followed by two LFOs, all without destination.
+    pub fn m8_default_mods() -> [Mod; SynthParams::MODULATOR_COUNT] {
+        let env = AHDEnv {
+            dest: 0,
+            amount: 0xFF,
+            attack: 0,
+            hold: 0,
+            decay: 0x80,
+            reserved: 0xFF,
+        };
+
+        let lfo = LFO {
+            shape: LfoShape::TRI,
+            dest: 0,
+            trigger_mode: LfoTriggerMode::FREE,
+            freq: 0x10,
+            amount: 0xFF,
+            retrigger: 0xFF,
+        };
+
+        [
+            env.clone().to_mod(),
+            env.to_mod(),
+            lfo.clone().to_mod(),
+            lfo.to_mod(),
+        ]
+    }
+
+    /// Zero the parameters of every modulator without destination,
+    /// keeping its kind, discarding the stale values left in its
+    /// unused fields.
+    pub fn normalize_mods(&mut self) {
+        for m in self.mods.iter_mut() {
+            if m.dest() == 0 {
+                *m = m.kind().zeroed();
+            }
+        }
+    }
+
+    /// Parameters of a freshly created instrument on the M8 (filter
+    /// fully open, centered pan and fine tune, dry at 0xC0...), unlike
+    /// a zeroed structure.
+    pub fn m8_default() -> Self {
+        Self {
+            volume: 0,
+            pitch: 0,
+            fine_tune: 0x80,
+
+            filter_type: 0,
+            filter_cutoff: 0xFF,
+            filter_res: 0,
+
+            amp: 0,
+            limit: LimitType(0),
+
+            mixer_pan: 0x80,
+            mixer_dry: 0xC0,
+            mixer_chorus: 0,
+            mixer_delay: 0,
+            mixer_reverb: 0,
+
+            associated_eq: 0xFF,
+            mod_padding: Vec::new(),
+
+            mods: SynthParams::m8_default_mods(),
+        }
+    }
+
     pub fn mod_only2(_reader: &mut Reader) -> M8Result<Self> {
         Ok(Self {
             volume: 0,
@@ -154,6 +344,7 @@ impl SynthParams {
             mixer_reverb: 0,
 
             associated_eq: 0xFF,
+            mod_padding: Vec::new(),
             mods: arr![AHDEnv::default().to_mod(); 4],
         })
     }
@@ -181,6 +372,7 @@ impl SynthParams {
             mixer_delay: 0,
             mixer_reverb: 0,
             associated_eq: 0xFF,
+            mod_padding: Vec::new(),
 
             mods,
         })
@@ -211,6 +403,7 @@ impl SynthParams {
             mixer_reverb: reader.read(),
 
             associated_eq: 0xFF,
+            mod_padding: Vec::new(),
 
             mods: [
                 AHDEnv::from_reader2(reader)?.to_mod(),
@@ -236,6 +429,13 @@ impl SynthParams {
         w.write(self.mixer_reverb);
 
         let writer_pos = w.pos();
+        if self.mod_padding.is_empty() {
+            w.write_bytes(&vec![0; mod_offset]);
+        } else {
+            debug_assert_eq!(self.mod_padding.len(), mod_offset, "mod_padding length");
+            w.write_bytes(&self.mod_padding);
+        }
+
         if ver.at_least(4, 1) {
             w.seek(writer_pos + mod_offset - 1);
             w.write(self.associated_eq);
@@ -276,17 +476,18 @@ impl SynthParams {
         let mixer_delay = reader.read();
         let mixer_reverb = reader.read();
 
-        let reader_pos = reader.pos();
+        let mut mod_padding = reader.read_bytes(mod_offset).to_vec();
         let associated_eq = if version.at_least(4, 1) {
-            reader.set_pos(reader_pos + mod_offset - 1);
-            reader.read()
+            std::mem::take(&mut mod_padding[mod_offset - 1])
         } else if version.at_least(4, 0) {
             eq
         } else {
             0xFF
         };
 
-        reader.set_pos(reader_pos + mod_offset);
+        if mod_padding.iter().all(|&b| b == 0) {
+            mod_padding.clear();
+        }
 
         let mods = arr![Mod::from_reader(reader)?; 4];
 
@@ -309,8 +510,143 @@ impl SynthParams {
             mixer_reverb,
 
             associated_eq,
+            mod_padding,
 
             mods,
         })
     }
 }
+
+/// Note number of A-4, tuned to 440Hz
+const A4_NOTE: u8 = 45;
+
+/// Cents of detune per unit of `fine_tune`, centered on 0x80: the
+/// full range covers one semitone below and above the note.
+pub const FINE_TUNE_CENTS_PER_UNIT: f32 = 100.0 / 128.0;
+
+/// Sounding frequency in Hz of `note` (0 being C-1, as displayed in
+/// phrases) played by an instrument with the given `pitch`, a signed
+/// offset in semitones, and `fine_tune`.
+pub fn note_frequency(note: u8, params: &SynthParams) -> f32 {
+    let semitones = note as f32 - A4_NOTE as f32 + params.pitch as i8 as f32;
+    let cents = (params.fine_tune as f32 - 128.0) * FINE_TUNE_CENTS_PER_UNIT;
+    440.0 * 2.0_f32.powf((semitones * 100.0 + cents) / 1200.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::instruments::common::*;
+
+    #[test]
+    fn transp_eq_keeps_high_bits() {
+        let ver = Version::default();
+        for byte in [0x00, 0x01, 0x80, 0xFE, 0xFF] {
+            assert_eq!(u8::from(TranspEq::from_version(ver, byte)), byte);
+        }
+    }
+
+    #[test]
+    fn default_tuning_a4_is_440() {
+        let mut params = SynthParams::m8_default();
+        assert!((note_frequency(45, &params) - 440.0).abs() < 0.01);
+
+        params.pitch = 12;
+        assert!((note_frequency(45, &params) - 880.0).abs() < 0.01);
+
+        params.pitch = -12i8 as u8;
+        params.fine_tune = 0;
+        assert!((note_frequency(46, &params) - 220.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn v2_transp_eq_has_no_eq() {
+        let v2 = Version {
+            major: 2,
+            minor: 7,
+            patch: 0,
+        };
+        assert_eq!(TranspEq::layout(v2), TranspEqLayout::TransposeOnly);
+        assert_eq!(TranspEq::layout(v2).eq_bits(), 0);
+        assert_eq!(
+            TranspEq::from_version(v2, 0x01),
+            TranspEq {
+                transpose: true,
+                eq: 0xFF
+            }
+        );
+        assert_eq!(u8::from(TranspEq::from(v2, true, 0x03)), 0x01);
+
+        assert_eq!(TranspEq::layout(Version::default()).eq_bits(), 7);
+    }
+
+    #[test]
+    fn pan_is_centered_on_0x80() {
+        let mut params = SynthParams::m8_default();
+        params.set_pan(0);
+        assert_eq!(params.mixer_pan, 0x80);
+        params.set_pan(-64);
+        assert_eq!(params.mixer_pan, 0x40);
+        assert_eq!(params.pan(), -64);
+        params.set_pan(i8::MIN);
+        assert_eq!(params.mixer_pan, 0x01);
+        params.set_pan(i8::MAX);
+        assert_eq!(params.mixer_pan, 0xFF);
+    }
+
+    #[test]
+    fn send_sets_matching_field() {
+        let mut params = SynthParams::m8_default();
+        params.set_send(MixerSend::Delay, 0x42);
+        params.set_send(MixerSend::Reverb, 0x24);
+        assert_eq!(params.mixer_delay, 0x42);
+        assert_eq!(params.mixer_reverb, 0x24);
+        assert_eq!(params.mixer_chorus, 0);
+    }
+
+    #[test]
+    fn empty_mod_padding_writes_zeroes() {
+        let ver = Version {
+            major: 4,
+            minor: 0,
+            patch: 0,
+        };
+        let params = SynthParams::m8_default();
+        assert!(params.mod_padding.is_empty());
+
+        let mod_offset = 3;
+        let mut w = Writer::new(vec![0xFF; 64]);
+        params.write(ver, &mut w, mod_offset);
+
+        // filter, amp, limit and mixer parameters come first
+        let padding_start = 10;
+        let bytes = w.finish();
+        assert_eq!(&bytes[padding_start..padding_start + mod_offset], &[0; 3]);
+    }
+
+    #[test]
+    fn mods_targeting_cutoff() {
+        let cutoff = 7;
+        let mut params = SynthParams::m8_default();
+        if let Mod::AHDEnv(env) = &mut params.mods[0] {
+            env.dest = cutoff;
+        }
+        if let Mod::LFO(lfo) = &mut params.mods[3] {
+            lfo.dest = cutoff;
+        }
+
+        assert_eq!(params.mods_targeting(cutoff), vec![0, 3]);
+        assert!(params.mods_targeting(1).is_empty());
+    }
+
+    #[test]
+    fn cleared_mod_is_empty_slot() {
+        let mut params = SynthParams::m8_default();
+        params.clear_mod(2).unwrap();
+        assert_eq!(params.mods[2].dest(), 0);
+        assert!(params.clear_mod(4).is_err());
+
+        let mut w = Writer::new(vec![0xFF; 6]);
+        params.mods[2].write(&mut w);
+        assert_eq!(w.finish(), vec![0; 6]);
+    }
+}
@@ -0,0 +1,260 @@
+use crate::eq::Equ;
+use crate::songs::V4_1_OFFSETS;
+use crate::version::Version;
+
+use super::Instrument;
+
+/// One differing byte of two instrument files, with the name of the
+/// field owning it.
+#[derive(PartialEq, Debug, Clone)]
+pub struct AnnotatedDiff {
+    /// Offset in the file
+    pub offset: usize,
+    pub old: u8,
+    pub new: u8,
+
+    /// Name of the field, as named in the instrument structures
+    pub field: &'static str,
+}
+
+/// List every (offset, old, new) bytes differing between two buffers.
+/// Bytes present in only one of the buffers are reported against 0.
+pub fn byte_diff(a: &[u8], b: &[u8]) -> Vec<(usize, u8, u8)> {
+    (0..a.len().max(b.len()))
+        .filter_map(|i| {
+            let old = a.get(i).copied().unwrap_or(0);
+            let new = b.get(i).copied().unwrap_or(0);
+            (old != new).then_some((i, old, new))
+        })
+        .collect()
+}
+
+/// Diff two instrument files (.m8i) and name the field of every
+/// differing byte, using the layout of the instrument kind of `a`.
+pub fn annotate_diff(a: &[u8], b: &[u8], ver: Version) -> Vec<AnnotatedDiff> {
+    let kind = a.get(Version::SIZE).copied().unwrap_or(0xFF);
+    byte_diff(a, b)
+        .into_iter()
+        .map(|(offset, old, new)| AnnotatedDiff {
+            offset,
+            old,
+            new,
+            field: field_at(kind, offset, ver),
+        })
+        .collect()
+}
+
+/// Offset of the modulators, identical for every instrument kind
+const MODS_OFFSET: usize = 0x3F;
+
+#[rustfmt::skip] // Keep constants with important order vertical for maintenance
+const MOD_FIELDS: [&str; 4] = [
+    "mods[0]",
+    "mods[1]",
+    "mods[2]",
+    "mods[3]",
+];
+
+#[rustfmt::skip] // Keep constants with important order vertical for maintenance
+const COMMON_FIELDS: [&str; 18] = [
+    "kind",
+    "name", "name", "name", "name", "name", "name",
+    "name", "name", "name", "name", "name", "name",
+    "transpose",
+    "table_tick",
+    "volume",
+    "pitch",
+    "fine_tune",
+];
+
+#[rustfmt::skip] // Keep constants with important order vertical for maintenance
+const SYNTH_PARAMS_FIELDS: [&str; 10] = [
+    "filter_type",
+    "filter_cutoff",
+    "filter_res",
+    "amp",
+    "limit",
+    "mixer_pan",
+    "mixer_dry",
+    "mixer_chorus",
+    "mixer_delay",
+    "mixer_reverb",
+];
+
+#[rustfmt::skip] // Keep constants with important order vertical for maintenance
+const WAVSYNTH_FIELDS: [&str; 5] = ["shape", "size", "mult", "warp", "scan"];
+
+#[rustfmt::skip] // Keep constants with important order vertical for maintenance
+const MACROSYNTH_FIELDS: [&str; 5] = ["shape", "timbre", "color", "degrade", "redux"];
+
+#[rustfmt::skip] // Keep constants with important order vertical for maintenance
+const SAMPLER_FIELDS: [&str; 6] = [
+    "play_mode", "slice", "start", "loop_start", "length", "degrade"
+];
+
+#[rustfmt::skip] // Keep constants with important order vertical for maintenance
+const FMSYNTH_FIELDS: [&str; 33] = [
+    "algo",
+    "operators.shape", "operators.shape", "operators.shape", "operators.shape",
+    "operators.ratio", "operators.ratio_fine", "operators.ratio", "operators.ratio_fine",
+    "operators.ratio", "operators.ratio_fine", "operators.ratio", "operators.ratio_fine",
+    "operators.level", "operators.feedback", "operators.level", "operators.feedback",
+    "operators.level", "operators.feedback", "operators.level", "operators.feedback",
+    "operators.mod_a", "operators.mod_a", "operators.mod_a", "operators.mod_a",
+    "operators.mod_b", "operators.mod_b", "operators.mod_b", "operators.mod_b",
+    "mod1", "mod2", "mod3", "mod4",
+];
+
+#[rustfmt::skip] // Keep constants with important order vertical for maintenance
+const HYPERSYNTH_FIELDS: [&str; 12] = [
+    "default_chord", "default_chord", "default_chord", "default_chord",
+    "default_chord", "default_chord", "default_chord",
+    "scale", "shift", "swarm", "width", "subosc",
+];
+
+#[rustfmt::skip] // Keep constants with important order vertical for maintenance
+const EXTERNAL_FIELDS: [&str; 13] = [
+    "input", "port", "channel", "bank", "program",
+    "cca", "cca", "ccb", "ccb", "ccc", "ccc", "ccd", "ccd",
+];
+
+#[rustfmt::skip] // Keep constants with important order vertical for maintenance
+const MIDIOUT_FIELDS: [&str; 42] = [
+    "kind",
+    "name", "name", "name", "name", "name", "name",
+    "name", "name", "name", "name", "name", "name",
+    "transpose",
+    "table_tick",
+    "port", "channel", "bank_select", "program_change",
+    "reserved", "reserved", "reserved",
+    "custom_cc", "custom_cc", "custom_cc", "custom_cc", "custom_cc",
+    "custom_cc", "custom_cc", "custom_cc", "custom_cc", "custom_cc",
+    "custom_cc", "custom_cc", "custom_cc", "custom_cc", "custom_cc",
+    "custom_cc", "custom_cc", "custom_cc", "custom_cc", "custom_cc",
+];
+
+/// Name of the field at `offset` of an instrument file whose instrument
+/// is of type `kind`. Only the 3.0+ layouts are described, older files
+/// report "unknown" past the fields common to every instrument.
+fn field_at(kind: u8, offset: usize, ver: Version) -> &'static str {
+    if offset < Version::SIZE {
+        return "version";
+    }
+
+    if let Some(eq) = V4_1_OFFSETS.instrument_file_eq_offset {
+        if ver.at_least(4, 0) && (eq..eq + Equ::V4_SIZE).contains(&offset) {
+            return "eq";
+        }
+    }
+
+    let pos = offset - Version::SIZE;
+    if pos >= Instrument::INSTRUMENT_MEMORY_SIZE {
+        return "table";
+    }
+
+    if !ver.at_least(3, 0) {
+        return COMMON_FIELDS.get(pos).copied().unwrap_or("unknown");
+    }
+
+    if (MODS_OFFSET..MODS_OFFSET + MOD_FIELDS.len() * 6).contains(&pos) {
+        return MOD_FIELDS[(pos - MODS_OFFSET) / 6];
+    }
+
+    if pos > MODS_OFFSET {
+        return match kind {
+            0x02 => "sample_path",
+            0x05 => "chords",
+            _ => "unused",
+        };
+    }
+
+    if kind == 0x03 {
+        return MIDIOUT_FIELDS.get(pos).copied().unwrap_or("mod_padding");
+    }
+
+    if let Some(field) = COMMON_FIELDS.get(pos) {
+        return field;
+    }
+
+    // Since 4.1 the EQ is stored in the last byte before the modulators
+    if pos == MODS_OFFSET - 1 && ver.at_least(4, 1) {
+        return "associated_eq";
+    }
+
+    let specific: &[&'static str] = match kind {
+        0x00 => &WAVSYNTH_FIELDS,
+        0x01 => &MACROSYNTH_FIELDS,
+        0x02 => &SAMPLER_FIELDS,
+        0x04 => &FMSYNTH_FIELDS,
+        0x05 => &HYPERSYNTH_FIELDS,
+        0x06 => &EXTERNAL_FIELDS,
+        _ => return "unknown",
+    };
+
+    let pos = pos - COMMON_FIELDS.len();
+    if let Some(field) = specific.get(pos) {
+        return field;
+    }
+
+    let pos = pos - specific.len();
+    SYNTH_PARAMS_FIELDS
+        .get(pos)
+        .copied()
+        .unwrap_or("mod_padding")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{field_at, MODS_OFFSET};
+    use crate::instruments::*;
+    use crate::reader::Reader;
+    use crate::{Version, V4_1_OFFSETS};
+
+    #[test]
+    fn byte_diff_reports_changed_bytes() {
+        assert_eq!(byte_diff(&[1, 2, 3], &[1, 4, 3]), vec![(1, 2, 4)]);
+        assert_eq!(byte_diff(&[1], &[1, 5]), vec![(1, 0, 5)]);
+    }
+
+    #[test]
+    fn single_field_edit_gives_one_annotation() {
+        let bytes = std::fs::read("./examples/instruments/FMDUBSTAB_4_1.m8i")
+            .expect("Could not open FMDUBSTAB_4_1");
+        let ver = Version::from_reader(&mut Reader::new(bytes.clone())).unwrap();
+        let instr = Instrument::try_from(&bytes[..]).unwrap();
+
+        let mut edited = instr.clone();
+        let params = edited.synth_params_mut().unwrap();
+        params.filter_cutoff = params.filter_cutoff.wrapping_add(1);
+
+        let diffs = annotate_diff(
+            &instr.write_m8i(ver, None),
+            &edited.write_m8i(ver, None),
+            ver,
+        );
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(diffs[0].field, "filter_cutoff");
+    }
+
+    #[test]
+    fn eq_and_old_layout_fields() {
+        let v4_1 = Version {
+            major: 4,
+            minor: 1,
+            patch: 0,
+        };
+        let eq = V4_1_OFFSETS.instrument_file_eq_offset.unwrap();
+        assert_eq!(field_at(0x00, eq, v4_1), "eq");
+        assert_eq!(field_at(0x00, eq - 1, v4_1), "table");
+
+        let v2 = Version {
+            major: 2,
+            minor: 7,
+            patch: 0,
+        };
+        let mods = Version::SIZE + MODS_OFFSET;
+        assert_eq!(field_at(0x02, mods, v2), "unknown");
+        assert_eq!(field_at(0x02, mods + 0x20, v2), "unknown");
+        assert_eq!(field_at(0x02, Version::SIZE + 1, v2), "name");
+    }
+}
@@ -1,6 +1,7 @@
 use super::common::SynthParams;
 use super::common::TranspEq;
 use super::dests;
+use super::lookup;
 use super::midi::ControlChange;
 use super::params;
 use super::CommandPack;
@@ -75,6 +76,42 @@ const DESTINATIONS : [&'static str; 14] = [
 impl ExternalInst {
     const MOD_OFFSET: usize = 22;
 
+    /// Instrument as initialized by the M8 when creating a new one
+    pub fn default_for(_ver: Version) -> Self {
+        Self {
+            number: 0,
+            name: String::new(),
+            transpose: true,
+            table_tick: 1,
+            synth_params: SynthParams {
+                fine_tune: 0,
+                ..SynthParams::m8_default()
+            },
+
+            input: 0,
+            port: 1,
+            channel: 1,
+            bank: 0xFF,
+            program: 0xFF,
+            cca: ControlChange {
+                number: 1,
+                value: 0xFF,
+            },
+            ccb: ControlChange {
+                number: 2,
+                value: 0xFF,
+            },
+            ccc: ControlChange {
+                number: 3,
+                value: 0xFF,
+            },
+            ccd: ControlChange {
+                number: 4,
+                value: 0xFF,
+            },
+        }
+    }
+
     pub fn command_name(&self, _ver: Version) -> &'static [&'static str] {
         &EXTERNAL_INST_COMMANDS
     }
@@ -90,7 +127,7 @@ impl ExternalInst {
 
     /// Return human readable name of the port.
     pub fn human_readable_port(&self) -> &'static str {
-        crate::instruments::midi::PORTS[self.port as usize]
+        lookup(&crate::instruments::midi::PORTS, self.port)
     }
 
     pub fn write(&self, ver: Version, w: &mut Writer) {
@@ -9,9 +9,10 @@ use num_enum::TryFromPrimitive;
 use arr_macro::arr;
 
 use super::dests;
+use super::lookup;
 use super::CommandPack;
 
-#[derive(Copy, Clone, PartialEq, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
 pub struct FmAlgo(pub u8);
 
 const FM_ALGO_STRINGS: [&str; 0x0C] = [
@@ -29,6 +30,25 @@ const FM_ALGO_STRINGS: [&str; 0x0C] = [
     "A+B+C+D",
 ];
 
+/// Modulation routing of each algorithm, as (modulator, modulated) pairs
+/// of operators numbered from A = 0 to D = 3. Operators not modulating
+/// anything are the carriers.
+#[rustfmt::skip] // Keep constats with important order vertical for maintenance
+const FM_ALGO_ROUTING: [&[(usize, usize)]; 0x0C] = [
+    &[(0, 1), (1, 2), (2, 3)],
+    &[(0, 2), (1, 2), (2, 3)],
+    &[(0, 1), (1, 3), (2, 3)],
+    &[(0, 1), (0, 2), (1, 3), (2, 3)],
+    &[(0, 3), (1, 3), (2, 3)],
+    &[(0, 1), (1, 2)],
+    &[(0, 1), (1, 2), (1, 3)],
+    &[(0, 1), (2, 3)],
+    &[(0, 1), (0, 2), (0, 3)],
+    &[(0, 1), (0, 2)],
+    &[(0, 1)],
+    &[],
+];
+
 impl TryFrom<u8> for FmAlgo {
     type Error = ParseError;
 
@@ -36,25 +56,154 @@ impl TryFrom<u8> for FmAlgo {
         if (value as usize) < FM_ALGO_STRINGS.len() {
             Ok(FmAlgo(value))
         } else {
-            Err(ParseError(format!("Invalid fm algo {}", value)))
+            Err(ParseError::new(
+                ParseErrorKind::InvalidAlgo(value),
+                format!("Invalid fm algo {}", value),
+            ))
         }
     }
 }
 
 impl FmAlgo {
+    /// Last valid algorithm
+    pub const MAX: FmAlgo = FmAlgo(FM_ALGO_STRINGS.len() as u8 - 1);
+
     pub fn id(self) -> u8 {
         let FmAlgo(v) = self;
         v
     }
 
     pub fn str(self) -> &'static str {
-        FM_ALGO_STRINGS[self.id() as usize]
+        lookup(&FM_ALGO_STRINGS, self.id())
+    }
+
+    /// Algorithm whose routing string (as rendered by [`FmAlgo::str`])
+    /// is `s`, spaces being ignored.
+    #[allow(clippy::should_implement_trait)] // Option is enough, no error to describe
+    pub fn from_str(s: &str) -> Option<FmAlgo> {
+        let routing: String = s.chars().filter(|c| !c.is_whitespace()).collect();
+        FM_ALGO_STRINGS
+            .iter()
+            .position(|algo| *algo == routing)
+            .map(|i| FmAlgo(i as u8))
+    }
+
+    /// Modulation links of the algorithm as (modulator, modulated) operator
+    /// indices, from A = 0 to D = 3.
+    pub fn routing(self) -> &'static [(usize, usize)] {
+        FM_ALGO_ROUTING
+            .get(self.id() as usize)
+            .copied()
+            .unwrap_or(&[])
+    }
+
+    /// Multi-line ASCII block diagram of the algorithm, modulators on
+    /// the left and carriers feeding the output on the right.
+    pub fn diagram(&self) -> String {
+        const BOX_WIDTH: usize = 5;
+        const COLUMN_WIDTH: usize = 11;
+        const ROW_HEIGHT: usize = 4;
+
+        let routing = self.routing();
+
+        // distance of each operator to the output, carriers being at 0
+        let mut layers = [0; 4];
+        for op in (0..4).rev() {
+            for &(from, to) in routing {
+                if from == op {
+                    layers[op] = layers[op].max(layers[to] + 1);
+                }
+            }
+        }
+
+        let columns = layers.iter().max().unwrap_or(&0) + 1;
+        let mut rows = vec![0; columns];
+        let mut positions = [(0, 0); 4];
+        for (op, layer) in layers.iter().enumerate() {
+            let column = columns - 1 - layer;
+            positions[op] = (column * COLUMN_WIDTH, rows[column] * ROW_HEIGHT);
+            rows[column] += 1;
+        }
+
+        let width = (columns - 1) * COLUMN_WIDTH + BOX_WIDTH + 8;
+        let height = rows.iter().max().unwrap_or(&1) * ROW_HEIGHT - 1;
+        let mut canvas = vec![vec![' '; width]; height];
+
+        fn plot(canvas: &mut [Vec<char>], x: usize, y: usize, c: char) {
+            let cell = &mut canvas[y][x];
+            *cell = if *cell == ' ' || *cell == c { c } else { '+' };
+        }
+
+        fn link(canvas: &mut [Vec<char>], from: (usize, usize), to: (usize, usize)) {
+            let turn = from.0 + 2;
+            for x in from.0..turn {
+                plot(canvas, x, from.1, '-');
+            }
+
+            if from.1 == to.1 {
+                plot(canvas, turn, from.1, '-');
+            } else {
+                plot(canvas, turn, from.1, '+');
+                for y in from.1.min(to.1) + 1..from.1.max(to.1) {
+                    plot(canvas, turn, y, '|');
+                }
+                plot(canvas, turn, to.1, '+');
+            }
+
+            for x in turn + 1..to.0 {
+                plot(canvas, x, to.1, '-');
+            }
+            plot(canvas, to.0, to.1, '>');
+        }
+
+        for (op, &(x, y)) in positions.iter().enumerate() {
+            let label = format!("| {} |", FMSynth::OPERATOR_NAMES[op]);
+            for (dx, ((top, mid), bottom)) in "+---+"
+                .chars()
+                .zip(label.chars())
+                .zip("+---+".chars())
+                .enumerate()
+            {
+                canvas[y][x + dx] = top;
+                canvas[y + 1][x + dx] = mid;
+                canvas[y + 2][x + dx] = bottom;
+            }
+        }
+
+        for &(from, to) in routing {
+            let (fx, fy) = positions[from];
+            let (tx, ty) = positions[to];
+            link(&mut canvas, (fx + BOX_WIDTH, fy + 1), (tx - 1, ty + 1));
+        }
+
+        let out_x = (columns - 1) * COLUMN_WIDTH;
+        for (op, &(x, y)) in positions.iter().enumerate() {
+            if !routing.iter().any(|&(from, _)| from == op) {
+                link(
+                    &mut canvas,
+                    (x + BOX_WIDTH, y + 1),
+                    (out_x + BOX_WIDTH + 4, 1),
+                );
+            }
+        }
+
+        for (dx, c) in "OUT".chars().enumerate() {
+            canvas[1][out_x + BOX_WIDTH + 5 + dx] = c;
+        }
+
+        canvas
+            .iter()
+            .map(|line| line.iter().collect::<String>().trim_end().to_string())
+            .collect::<Vec<_>>()
+            .join("\n")
     }
 }
 
 #[repr(u8)]
 #[allow(non_camel_case_types)]
-#[derive(IntoPrimitive, TryFromPrimitive, PartialEq, Copy, Clone, Default, Debug)]
+#[derive(
+    IntoPrimitive, TryFromPrimitive, PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Default, Debug,
+)]
 pub enum FMWave {
     #[default]
     SIN,
@@ -137,6 +286,16 @@ pub enum FMWave {
     W45,
 }
 
+impl FMWave {
+    /// Number of waves available before firmware 4.1 (up to `CLK`)
+    pub const PRE_4_1_COUNT: u8 = 16;
+
+    /// Tell if the wave is one of the waves added by firmware 4.1
+    pub fn is_extended(self) -> bool {
+        u8::from(self) >= FMWave::PRE_4_1_COUNT
+    }
+}
+
 #[rustfmt::skip] // Keep constants with important order vertical for maintenance
 const FM_FX_BASE_COMMANDS : [&'static str; CommandPack::BASE_INSTRUMENT_COMMAND_COUNT] = [
     "VOL",
@@ -200,6 +359,50 @@ pub struct Operator {
     pub mod_b: u8,
 }
 
+impl Operator {
+    /// Operator level as an amplitude in dB relative to the maximum:
+    /// 0x00 is off (-inf), 0xFF is 0dB, and 0x80 is about -6dB
+    /// (`20 * log10(level / 255)`).
+    pub fn level_db(&self) -> f32 {
+        if self.level == 0 {
+            f32::NEG_INFINITY
+        } else {
+            20.0 * (self.level as f32 / 255.0).log10()
+        }
+    }
+
+    /// Feedback amount as a ratio, from 0.0 (0x00, no feedback) to
+    /// 1.0 (0xFF).
+    pub fn feedback_ratio(&self) -> f32 {
+        self.feedback as f32 / 255.0
+    }
+
+    /// Move the fine ratio by `delta`, carrying into (or borrowing from)
+    /// the coarse ratio as the device treats them as one coarse.fine
+    /// value. The result saturates at 00.00 and FF.FF.
+    pub fn bump_ratio_fine(&mut self, delta: i16) {
+        let combined = (self.ratio as i32) << 8 | self.ratio_fine as i32;
+        let bumped = (combined + delta as i32).clamp(0, 0xFFFF);
+        self.ratio = (bumped >> 8) as u8;
+        self.ratio_fine = (bumped & 0xFF) as u8;
+    }
+}
+
+/// Raw value of one of the four global MOD controls of the FM synth,
+/// labelled with the modulation destination and instrument FX command
+/// addressing it. The operator parameters it drives are picked by the
+/// `mod_a`/`mod_b` bytes of each [`Operator`], which are not decoded.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct FmGlobalMod {
+    /// Modulation destination name, "MOD1" to "MOD4"
+    pub destination: &'static str,
+
+    /// FX command addressing it, "FM1" to "FM4"
+    pub command: &'static str,
+
+    pub value: u8,
+}
+
 #[derive(PartialEq, Debug, Clone)]
 pub struct FMSynth {
     pub number: u8,
@@ -217,7 +420,86 @@ pub struct FMSynth {
 }
 
 impl FMSynth {
-    const MOD_OFFSET: usize = 2;
+    pub(crate) const MOD_OFFSET: usize = 2;
+
+    /// Operator labels, as used by the algorithm strings
+    pub const OPERATOR_NAMES: [char; 4] = ['A', 'B', 'C', 'D'];
+
+    pub fn set_algo(&mut self, algo: FmAlgo) {
+        self.algo = algo;
+    }
+
+    /// Operators along with their label ('A' to 'D')
+    pub fn operators_indexed(&self) -> impl Iterator<Item = (char, &Operator)> {
+        FMSynth::OPERATOR_NAMES
+            .into_iter()
+            .zip(self.operators.iter())
+    }
+
+    /// Mutable variant of [`FMSynth::operators_indexed`]
+    pub fn operators_indexed_mut(&mut self) -> impl Iterator<Item = (char, &mut Operator)> {
+        FMSynth::OPERATOR_NAMES
+            .into_iter()
+            .zip(self.operators.iter_mut())
+    }
+
+    /// Operators (from A = 0 to D = 3) with an audible feedback loop:
+    /// a non zero feedback, and a path of the algorithm routing from
+    /// the operator to the output going only through operators of non
+    /// zero level, an operator at level 0 silencing what it carries.
+    pub fn feedback_operators(&self) -> Vec<usize> {
+        let routing = self.algo.routing();
+
+        // routing links always go from an operator to a later one, so
+        // walking backward sees the modulated operators first
+        let mut heard = [false; 4];
+        for op in (0..self.operators.len()).rev() {
+            let mut targets = routing.iter().filter(|&&(from, _)| from == op).peekable();
+            let is_carrier = targets.peek().is_none();
+            let reaches_out = is_carrier || targets.any(|&(_, to)| heard[to]);
+            heard[op] = self.operators[op].level != 0 && reaches_out;
+        }
+
+        (0..self.operators.len())
+            .filter(|&op| heard[op] && self.operators[op].feedback != 0)
+            .collect()
+    }
+
+    /// Modulation graph of the instrument as (modulator, modulated)
+    /// pairs: the routing of the algorithm, plus a self loop for each
+    /// of the [`FMSynth::feedback_operators`].
+    pub fn routing_graph(&self) -> Vec<(usize, usize)> {
+        let mut graph = self.algo.routing().to_vec();
+        graph.extend(self.feedback_operators().into_iter().map(|op| (op, op)));
+        graph
+    }
+
+    /// Instrument as initialized by the M8 when creating a new one
+    pub fn default_for(_ver: Version) -> Self {
+        Self {
+            number: 0,
+            name: String::new(),
+            transpose: true,
+            table_tick: 1,
+            synth_params: SynthParams::m8_default(),
+
+            algo: FmAlgo(0),
+            operators: arr![Operator {
+                shape: FMWave::SIN,
+                ratio: 1,
+                ratio_fine: 0,
+                level: 0x80,
+                feedback: 0,
+                retrigger: 0,
+                mod_a: 0,
+                mod_b: 0,
+            }; 4],
+            mod1: 0,
+            mod2: 0,
+            mod3: 0,
+            mod4: 0,
+        }
+    }
 
     pub fn command_name(&self, ver: Version) -> &'static [&'static str] {
         if ver.at_least(6, 0) {
@@ -231,13 +513,24 @@ impl FMSynth {
         &DESTINATIONS
     }
 
+    /// The MOD1 to MOD4 values with the destination and command
+    /// controlling them, as stored.
+    pub fn global_mod_values(&self) -> [FmGlobalMod; 4] {
+        let values = [self.mod1, self.mod2, self.mod3, self.mod4];
+        std::array::from_fn(|i| FmGlobalMod {
+            destination: DESTINATIONS[3 + i],
+            command: FM_FX_BASE_COMMANDS[4 + i],
+            value: values[i],
+        })
+    }
+
     /// List of all the applyable filter types for the instrument
     pub fn filter_types(&self, _ver: Version) -> &'static [&'static str] {
         &COMMON_FILTER_TYPES
     }
 
     pub fn human_readable_filter(&self) -> &'static str {
-        COMMON_FILTER_TYPES[self.synth_params.filter_type as usize]
+        lookup(&COMMON_FILTER_TYPES, self.synth_params.filter_type)
     }
 
     pub fn write(&self, ver: Version, w: &mut Writer) {
@@ -298,8 +591,21 @@ impl FMSynth {
         if version.at_least(1, 4) {
             for i in 0..4 {
                 let wav_code = reader.read();
-                operators[i].shape = FMWave::try_from(wav_code)
-                    .map_err(|_| ParseError(format!("Invalid fm wave {}", wav_code)))?;
+                if wav_code >= FMWave::PRE_4_1_COUNT && !version.at_least(4, 1) {
+                    return Err(ParseError::new(
+                        ParseErrorKind::InvalidFmWave(wav_code),
+                        format!(
+                            "Fm wave {} requires firmware 4.1 (got {})",
+                            wav_code, version
+                        ),
+                    ));
+                }
+                operators[i].shape = FMWave::try_from(wav_code).map_err(|_| {
+                    ParseError::new(
+                        ParseErrorKind::InvalidFmWave(wav_code),
+                        format!("Invalid fm wave {}", wav_code),
+                    )
+                })?;
             }
         }
         for i in 0..4 {
@@ -351,3 +657,173 @@ impl FMSynth {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::instruments::*;
+
+    #[test]
+    fn algo_from_routing_string() {
+        let algo = FmAlgo::from_str("[A>B]+[C>D]").unwrap();
+        assert_eq!(algo, FmAlgo(0x07));
+        assert_eq!(FmAlgo::from_str(" A + B + C + D "), Some(FmAlgo(0x0B)));
+        assert_eq!(FmAlgo::from_str("A>B>C>D>E"), None);
+
+        let mut fm = FMSynth::default_for(Version::default());
+        fm.set_algo(algo);
+        assert_eq!(fm.algo.str(), "[A>B]+[C>D]");
+    }
+
+    #[test]
+    fn feedback_adds_self_loop_to_routing() {
+        let mut fm = FMSynth::default_for(Version::default());
+        fm.set_algo(FmAlgo(0x07));
+        for op in fm.operators.iter_mut() {
+            op.feedback = 0;
+        }
+        assert!(fm.feedback_operators().is_empty());
+        assert_eq!(fm.routing_graph(), vec![(0, 1), (2, 3)]);
+
+        fm.operators[1].feedback = 0x40;
+        assert_eq!(fm.feedback_operators(), vec![1]);
+        assert_eq!(fm.routing_graph(), vec![(0, 1), (2, 3), (1, 1)]);
+
+        // C only reaches the output through D in [A>B]+[C>D]
+        fm.operators[2].feedback = 0x20;
+        fm.operators[3].level = 0;
+        assert_eq!(fm.feedback_operators(), vec![1]);
+
+        fm.set_algo(FmAlgo(0x0B));
+        assert_eq!(fm.feedback_operators(), vec![1, 2]);
+        assert_eq!(fm.routing_graph(), vec![(1, 1), (2, 2)]);
+    }
+
+    #[test]
+    fn extended_wave_boundary() {
+        assert!(!FMWave::CLK.is_extended());
+        assert!(FMWave::W09.is_extended());
+        assert_eq!(u8::from(FMWave::W09), FMWave::PRE_4_1_COUNT);
+        assert!(FMWave::CLK < FMWave::W09);
+
+        assert!(FmAlgo(0x0B) <= FmAlgo::MAX);
+        assert!(FmAlgo(0x0C) > FmAlgo::MAX);
+    }
+
+    #[test]
+    fn ratio_fine_rolls_into_coarse() {
+        let mut op = Operator {
+            ratio: 0x01,
+            ratio_fine: 0xFF,
+            ..Operator::default()
+        };
+        op.bump_ratio_fine(1);
+        assert_eq!((op.ratio, op.ratio_fine), (0x02, 0x00));
+
+        op.bump_ratio_fine(-2);
+        assert_eq!((op.ratio, op.ratio_fine), (0x01, 0xFE));
+
+        op.bump_ratio_fine(-0x7FFF);
+        assert_eq!((op.ratio, op.ratio_fine), (0x00, 0x00));
+    }
+
+    #[test]
+    fn operator_labels_match_algo_routing() {
+        let mut fm = FMSynth::default_for(Version::default());
+        for (label, op) in fm.operators_indexed_mut() {
+            op.level = label as u8;
+        }
+
+        let labels: Vec<char> = fm.operators_indexed().map(|(c, _)| c).collect();
+        let parallel: Vec<char> = FmAlgo(0x0B).str().chars().filter(|c| *c != '+').collect();
+        assert_eq!(labels, parallel);
+
+        let by_label = |c: char| fm.operators_indexed().find(|(l, _)| *l == c).unwrap().1;
+        for &(from, to) in FmAlgo(0).routing() {
+            assert_eq!(by_label(FMSynth::OPERATOR_NAMES[from]), &fm.operators[from]);
+            assert_eq!(by_label(FMSynth::OPERATOR_NAMES[to]).level, b'A' + to as u8);
+        }
+    }
+
+    #[test]
+    fn chain_algo_diagram() {
+        let diagram = FmAlgo(0).diagram();
+        let lines: Vec<&str> = diagram.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0].matches("+---+").count(), 4);
+        assert_eq!(lines[1], "| A |----->| B |----->| C |----->| D |---->OUT");
+        assert_eq!(lines[0], lines[2]);
+    }
+
+    #[test]
+    fn every_algo_diagram_has_four_operators() {
+        for algo in 0..0x0C {
+            let diagram = FmAlgo(algo).diagram();
+            for op in ["| A |", "| B |", "| C |", "| D |"] {
+                assert_eq!(diagram.matches(op).count(), 1, "{}", diagram);
+            }
+            assert!(diagram.contains("OUT"));
+        }
+    }
+
+    #[test]
+    fn extended_wave_needs_4_1() {
+        // name, transpose/eq, table tick, volume, pitch, fine tune, algo
+        let wave_offset = 12 + 6;
+        let mut buf = vec![0; Instrument::INSTRUMENT_MEMORY_SIZE];
+        buf[wave_offset] = 0x20;
+
+        let v4_0 = Version {
+            major: 4,
+            minor: 0,
+            patch: 0,
+        };
+        let err = FMSynth::from_reader(v4_0, &mut Reader::new(buf.clone()), 0, v4_0).unwrap_err();
+        assert_eq!(err.kind(), ParseErrorKind::InvalidFmWave(0x20));
+
+        let v4_1 = Version {
+            major: 4,
+            minor: 1,
+            patch: 0,
+        };
+        let fm = FMSynth::from_reader(v4_1, &mut Reader::new(buf), 0, v4_1).unwrap();
+        assert_eq!(fm.operators[0].shape, FMWave::try_from(0x20).unwrap());
+    }
+
+    #[test]
+    fn operator_level_db() {
+        let op = |level| Operator {
+            level,
+            feedback: level,
+            ..Operator::default()
+        };
+
+        assert_eq!(op(0).level_db(), f32::NEG_INFINITY);
+        assert_eq!(op(0xFF).level_db(), 0.0);
+        assert!((op(0x80).level_db() + 5.99).abs() < 0.01);
+
+        assert_eq!(op(0).feedback_ratio(), 0.0);
+        assert_eq!(op(0xFF).feedback_ratio(), 1.0);
+    }
+
+    #[test]
+    fn global_mod_values_labels() {
+        let mut fm = FMSynth::default_for(Version::default());
+        fm.mod1 = 0x10;
+        fm.mod2 = 0x20;
+        fm.mod3 = 0x30;
+        fm.mod4 = 0xFF;
+
+        let mods = fm.global_mod_values();
+        assert_eq!(
+            mods[0],
+            FmGlobalMod {
+                destination: "MOD1",
+                command: "FM1",
+                value: 0x10
+            }
+        );
+        assert_eq!(mods[3].destination, "MOD4");
+        assert_eq!(mods[3].command, "FM4");
+        assert_eq!(mods.map(|m| m.value), [0x10, 0x20, 0x30, 0xFF]);
+    }
+}
@@ -2,10 +2,13 @@ use super::common::SynthParams;
 use super::common::TranspEq;
 use super::common::COMMON_FILTER_TYPES;
 use super::dests;
+use super::lookup;
 use super::CommandPack;
 use super::Version;
 use crate::reader::*;
 use crate::writer::Writer;
+use crate::Note;
+use crate::Scale;
 
 use arr_macro::arr;
 
@@ -104,6 +107,36 @@ const DESTINATIONS : [&'static str; 15] = [
 impl HyperSynth {
     const MOD_OFFSET: usize = 23;
 
+    /// Number of notes played by a chord, one oscillator each.
+    pub const BASE_VOICES: u8 = 6;
+
+    /// Estimated voice count with both swarm and sub oscillator enabled,
+    /// see [`HyperSynth::estimated_voice_count`].
+    pub const MAX_VOICES: u8 = HyperSynth::BASE_VOICES * 3;
+
+    /// Instrument as initialized by the M8 when creating a new one
+    pub fn default_for(_ver: Version) -> Self {
+        Self {
+            number: 0,
+            name: String::new(),
+            transpose: true,
+            table_tick: 1,
+            synth_params: SynthParams::m8_default(),
+
+            scale: 0,
+            default_chord: [0, 0, 1, 2, 4, 0xFF, 0],
+            shift: 0x80,
+            swarm: 0,
+            width: 0,
+            subosc: 0x80,
+            chords: {
+                let mut chords = [[0; 6]; 0x10];
+                chords[0] = [0, 1, 2, 4, 0xFF, 0];
+                chords
+            },
+        }
+    }
+
     pub fn command_name(&self, ver: Version) -> &'static [&'static str] {
         if ver.at_least(6, 0) {
             &HYPERSYNTH_COMMAND_NAMES_6
@@ -122,7 +155,7 @@ impl HyperSynth {
     }
 
     pub fn human_readable_filter(&self) -> &'static str {
-        COMMON_FILTER_TYPES[self.synth_params.filter_type as usize]
+        lookup(&COMMON_FILTER_TYPES, self.synth_params.filter_type)
     }
 
     pub fn write(&self, ver: Version, w: &mut Writer) {
@@ -153,6 +186,60 @@ impl HyperSynth {
         }
     }
 
+    /// Note names of the notes played by a chord, as resolved by
+    /// [`HyperSynth::effective_chord`] through `scale` and the shift,
+    /// with the root on C. Returns an empty list for an invalid chord
+    /// index.
+    pub fn chord_names(&self, idx: usize, scale: &Scale) -> Vec<String> {
+        self.effective_chord(idx, scale)
+            .into_iter()
+            .map(|n| Note(n).to_string())
+            .collect()
+    }
+
+    /// Rough estimate of the oscillators running for a played chord,
+    /// `BASE_VOICES * (1 + (swarm > 0) + (subosc > 0))`. The firmware
+    /// documentation gives no voice count, this only assumes a non zero
+    /// swarm adds one detuned copy of each chord note and a non zero
+    /// subosc one sub oscillator. Do not rely on it for CPU budgeting.
+    pub fn estimated_voice_count(&self) -> u8 {
+        let per_note = 1 + u8::from(self.swarm > 0) + u8::from(self.subosc > 0);
+        HyperSynth::BASE_VOICES * per_note
+    }
+
+    /// Semitone offsets from the root of the notes played by a chord.
+    /// Chord entries are degrees of the instrument scale (`scale` being
+    /// the song scale selected by `self.scale`), moved by `shift`
+    /// degrees centered on 0x80, a degree past the enabled notes of the
+    /// scale wrapping to the next octave. Empty (0xFF) entries are
+    /// skipped, and an invalid chord index gives an empty list.
+    pub fn effective_chord(&self, idx: usize, scale: &Scale) -> Vec<u8> {
+        let degrees: Vec<i16> = scale
+            .notes
+            .iter()
+            .enumerate()
+            .filter(|(_, n)| n.enabled)
+            .map(|(semitone, _)| semitone as i16)
+            .collect();
+
+        let (Some(chord), false) = (self.chords.get(idx), degrees.is_empty()) else {
+            return vec![];
+        };
+
+        let count = degrees.len() as i16;
+        let shift = self.shift as i16 - 0x80;
+        chord
+            .iter()
+            .filter(|&&n| n != 0xFF)
+            .map(|&n| {
+                let degree = n as i16 + shift;
+                let semitone =
+                    degrees[degree.rem_euclid(count) as usize] + 12 * degree.div_euclid(count);
+                semitone.clamp(0, 0x7F) as u8
+            })
+            .collect()
+    }
+
     fn load_chord(reader: &mut Reader) -> [u8; 6] {
         // padding
         let _ = reader.read();
@@ -202,3 +289,49 @@ impl HyperSynth {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::instruments::*;
+    use crate::Scale;
+
+    #[test]
+    fn major_triad_chord_names() {
+        let mut hs = HyperSynth::default_for(Version::default());
+        hs.chords[3] = [0x00, 0x04, 0x07, 0xFF, 0xFF, 0xFF];
+
+        let chromatic = Scale::default();
+        assert_eq!(hs.chord_names(3, &chromatic), vec!["C-1", "E-1", "G-1"]);
+        assert!(hs.chord_names(0x10, &chromatic).is_empty());
+    }
+
+    #[test]
+    fn estimated_voice_count_bounds() {
+        let mut hs = HyperSynth::default_for(Version::default());
+        hs.swarm = 0;
+        hs.subosc = 0;
+        assert_eq!(hs.estimated_voice_count(), HyperSynth::BASE_VOICES);
+
+        hs.swarm = 0xFF;
+        hs.subosc = 0xFF;
+        assert_eq!(hs.estimated_voice_count(), HyperSynth::MAX_VOICES);
+    }
+
+    #[test]
+    fn chord_in_minor_scale_with_shift() {
+        let mut minor = Scale::default();
+        for semitone in [1, 4, 6, 9, 11] {
+            minor.notes[semitone].enabled = false;
+        }
+
+        let mut hs = HyperSynth::default_for(Version::default());
+        hs.chords[1] = [0, 2, 4, 7, 0xFF, 0xFF];
+        assert_eq!(hs.effective_chord(1, &minor), vec![0, 3, 7, 12]);
+
+        assert_eq!(hs.chord_names(1, &minor), vec!["C-1", "D#1", "G-1", "C-2"]);
+
+        hs.shift = 0x81;
+        assert_eq!(hs.effective_chord(1, &minor), vec![2, 5, 8, 14]);
+        assert!(hs.effective_chord(0x10, &minor).is_empty());
+    }
+}
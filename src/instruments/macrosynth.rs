@@ -6,6 +6,7 @@ use num_enum::IntoPrimitive;
 use num_enum::TryFromPrimitive;
 
 use super::dests;
+use super::lookup;
 use super::CommandPack;
 
 /// Macro synth oscilator modes.
@@ -129,6 +130,23 @@ pub struct MacroSynth {
 impl MacroSynth {
     pub const MOD_OFFSET: usize = 30;
 
+    /// Instrument as initialized by the M8 when creating a new one
+    pub fn default_for(_ver: Version) -> Self {
+        Self {
+            number: 0,
+            name: String::new(),
+            transpose: true,
+            table_tick: 1,
+            synth_params: SynthParams::m8_default(),
+
+            shape: MacroSynthOsc::CSAW,
+            timbre: 0x80,
+            color: 0x80,
+            degrade: 0,
+            redux: 0,
+        }
+    }
+
     pub fn command_name(&self, _ver: Version) -> &'static [&'static str] {
         &MACRO_SYNTH_COMMANDS
     }
@@ -143,7 +161,7 @@ impl MacroSynth {
     }
 
     pub fn human_readable_filter(&self) -> &'static str {
-        COMMON_FILTER_TYPES[self.synth_params.filter_type as usize]
+        lookup(&COMMON_FILTER_TYPES, self.synth_params.filter_type)
     }
 
     pub fn write(&self, ver: Version, w: &mut Writer) {
@@ -208,9 +226,12 @@ impl MacroSynth {
             synth_params,
 
             shape: shape.try_into().map_err(|_| {
-                ParseError(format!(
-                    "I{number:X} Wrong macrosynth@{ms_pos} ({nc}) shape {shape}@0x{ofs_shape}"
-                ))
+                ParseError::new(
+                    ParseErrorKind::InvalidValue(shape),
+                    format!(
+                        "I{number:X} Wrong macrosynth@{ms_pos} ({nc}) shape {shape}@0x{ofs_shape}"
+                    ),
+                )
             })?,
             timbre,
             color,
@@ -4,8 +4,11 @@ use crate::version::*;
 use crate::writer::Writer;
 
 use arr_macro::arr;
+use num_enum::IntoPrimitive;
+use num_enum::TryFromPrimitive;
 
 use super::dests;
+use super::lookup;
 use super::params;
 use super::CommandPack;
 
@@ -79,6 +82,24 @@ pub(crate) const PORTS : [&'static str; 4] = [
     "INTERNAL"
 ];
 
+/// Output port of a MIDI out instrument, in the order of [`PORTS`]
+#[repr(u8)]
+#[derive(IntoPrimitive, TryFromPrimitive, PartialEq, Copy, Clone, Default, Debug)]
+pub enum MidiPort {
+    #[default]
+    MidiUsb,
+    Midi,
+    Usb,
+    Internal,
+}
+
+impl MidiPort {
+    /// Name of the port as displayed by the M8
+    pub fn name(self) -> &'static str {
+        PORTS[self as usize]
+    }
+}
+
 #[derive(PartialEq, Debug, Clone)]
 pub struct MIDIOut {
     pub number: u8,
@@ -92,12 +113,45 @@ pub struct MIDIOut {
     pub program_change: u8,
     pub custom_cc: [ControlChange; 10],
 
+    /// Unidentified bytes between the program change and the CCs,
+    /// kept to write them back as read.
+    pub reserved: [u8; 3],
+
     pub mods: SynthParams,
 }
 
 impl MIDIOut {
     const MOD_OFFSET: usize = 21;
 
+    /// Instrument as initialized by the M8 when creating a new one
+    pub fn default_for(_ver: Version) -> Self {
+        Self {
+            number: 0,
+            name: String::new(),
+            transpose: true,
+            table_tick: 1,
+
+            port: 0,
+            channel: 1,
+            bank_select: 0xFF,
+            program_change: 0xFF,
+            custom_cc: [ControlChange {
+                number: 0xFF,
+                value: 0xFF,
+            }; 10],
+            reserved: [0; 3],
+
+            // only the modulators are stored for MIDI out
+            mods: SynthParams {
+                fine_tune: 0,
+                filter_cutoff: 0,
+                mixer_pan: 0,
+                mixer_dry: 0,
+                ..SynthParams::m8_default()
+            },
+        }
+    }
+
     pub fn port_names(_ver: Version) -> &'static [&'static str] {
         &PORTS
     }
@@ -110,8 +164,18 @@ impl MIDIOut {
         &DESTINATIONS
     }
 
+    /// Typed output port, None if the stored value is out of range
+    pub fn midi_port(&self) -> Option<MidiPort> {
+        MidiPort::try_from(self.port).ok()
+    }
+
+    /// MIDI channel, None if not in the valid 1 to 16 range
+    pub fn midi_channel(&self) -> Option<u8> {
+        (1..=16).contains(&self.channel).then_some(self.channel)
+    }
+
     pub fn human_readable_port(&self) -> &'static str {
-        PORTS[self.port as usize]
+        lookup(&PORTS, self.port)
     }
 
     pub fn write(&self, _ver: Version, w: &mut Writer) {
@@ -123,7 +187,7 @@ impl MIDIOut {
         w.write(self.bank_select);
         w.write(self.program_change);
 
-        w.skip(3);
+        w.write_bytes(&self.reserved);
 
         for cc in self.custom_cc {
             cc.write(w);
@@ -146,7 +210,7 @@ impl MIDIOut {
         let channel = reader.read();
         let bank_select = reader.read();
         let program_change = reader.read();
-        reader.read_bytes(3); // discard
+        let reserved = arr![reader.read(); 3];
         let custom_cc = arr![ControlChange::from_reader(reader)?; 10];
         let mods = if version.at_least(3, 0) {
             SynthParams::mod_only3(reader, MIDIOut::MOD_OFFSET)?
@@ -165,6 +229,7 @@ impl MIDIOut {
             bank_select,
             program_change,
             custom_cc,
+            reserved,
             mods,
         })
     }
@@ -1,21 +1,30 @@
 use crate::eq::Equ;
 use crate::reader::*;
 use crate::version::*;
-use crate::writer::Writer;
+use crate::writer::{fnv1a, Writer, FNV_OFFSET};
+use crate::Table;
 use crate::V4_1_OFFSETS;
 
+mod bank;
 mod common;
+mod diff;
 mod external_inst;
 mod fmsynth;
 mod hypersynth;
 mod macrosynth;
 mod midi;
 mod modulator;
+mod morph;
 mod sampler;
 mod wavsynth;
 
-pub use common::{LimitType, SynthParams};
+pub use common::{
+    note_frequency, LimitType, MixerSend, SynthParams, TranspEq, TranspEqLayout,
+    FINE_TUNE_CENTS_PER_UNIT,
+};
 
+pub use bank::*;
+pub use diff::*;
 pub use external_inst::*;
 pub use fmsynth::*;
 pub use hypersynth::*;
@@ -155,11 +164,30 @@ impl CommandPack {
 
         None
     }
+
+    /// Command byte of an instrument command mnemonic, inverse of
+    /// `try_render`.
+    pub fn find_command(self, name: &str) -> Option<u8> {
+        (CommandPack::INSTRUMENT_COMMAND_OFFSET as u8..0xFF)
+            .find(|&cmd| self.accepts(cmd) && self.try_render(cmd) == Some(name))
+    }
+}
+
+/// Inconsistency found in an instrument by [`Instrument::validate`]
+#[derive(PartialEq, Debug, Clone)]
+pub enum InstrumentWarning {
+    /// Modulator targeting a destination index the synth doesn't have,
+    /// typically left by a conversion from another instrument kind.
+    UnknownModDestination { slot: usize, dest: u8 },
+
+    /// MIDI out channel outside of the 1 to 16 range
+    InvalidMidiChannel { channel: u8 },
 }
 
 /// Firmware 4.1 introduce files with an instrument definition and an
 /// EQ. This structure represent the result of parsing such insturment
 /// with an optional EQ.
+#[derive(PartialEq, Clone)]
 pub struct InstrumentWithEq {
     /// The parsed instrument
     pub instrument: Instrument,
@@ -167,12 +195,142 @@ pub struct InstrumentWithEq {
     /// If the instrument was referencing an EQ, the effectively
     /// parsed EQ.
     pub eq: Option<Equ>,
+
+    /// Instrument table stored right after the instrument definition,
+    /// if the file is long enough to contain it.
+    pub table: Option<Table>,
+}
+
+impl InstrumentWithEq {
+    /// Write back the instrument, its table and EQ in a buffer loaded
+    /// from an instrument file.
+    pub fn write(&self, ver: Version, w: &mut Writer) {
+        w.seek(Version::SIZE);
+        self.instrument.write(ver, w);
+
+        if let Some(table) = &self.table {
+            w.seek(Version::SIZE + Instrument::INSTRUMENT_MEMORY_SIZE);
+            table.write(w);
+        }
+
+        match (&self.eq, V4_1_OFFSETS.instrument_file_eq_offset) {
+            (Some(eq), Some(ofs)) if ver.at_least(4, 0) => {
+                w.seek(ofs);
+                eq.write(w);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Name at index `idx` of a static name table, "???" if the index is
+/// out of range (corrupted file or newer firmware).
+pub fn lookup(table: &[&'static str], idx: u8) -> &'static str {
+    table.get(idx as usize).copied().unwrap_or("???")
+}
+
+/// Type specific callbacks over instruments, to analyze a bank
+/// without matching on [`Instrument`] by hand. Every callback does
+/// nothing by default, implement only the ones you need.
+pub trait InstrumentVisitor {
+    fn visit_wavsynth(&mut self, _slot: usize, _instr: &WavSynth) {}
+    fn visit_macrosynth(&mut self, _slot: usize, _instr: &MacroSynth) {}
+    fn visit_sampler(&mut self, _slot: usize, _instr: &Sampler) {}
+    fn visit_midi_out(&mut self, _slot: usize, _instr: &MIDIOut) {}
+    fn visit_fm(&mut self, _slot: usize, _instr: &FMSynth) {}
+    fn visit_hypersynth(&mut self, _slot: usize, _instr: &HyperSynth) {}
+    fn visit_external(&mut self, _slot: usize, _instr: &ExternalInst) {}
+    fn visit_empty(&mut self, _slot: usize) {}
+}
+
+/// Call `f` on every instrument of the bank along with its slot.
+pub fn visit_instruments(bank: &[Instrument], mut f: impl FnMut(usize, &Instrument)) {
+    for (slot, instr) in bank.iter().enumerate() {
+        f(slot, instr)
+    }
+}
+
+/// Dispatch every instrument of the bank to the matching visitor callback.
+pub fn visit_bank<V: InstrumentVisitor>(bank: &[Instrument], visitor: &mut V) {
+    visit_instruments(bank, |slot, instr| instr.accept(slot, visitor))
+}
+
+/// Maximum length in bytes of an instrument name
+pub const INSTRUMENT_NAME_LENGTH: usize = 12;
+
+/// Make a name storable by the M8: characters outside of printable
+/// ASCII are replaced by '_' and the result is cut to
+/// `INSTRUMENT_NAME_LENGTH` bytes.
+pub fn sanitize_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c == ' ' || c.is_ascii_graphic() {
+                c
+            } else {
+                '_'
+            }
+        })
+        .take(INSTRUMENT_NAME_LENGTH)
+        .collect()
+}
+
+/// Rename every instrument of the bank with `f`, called with the
+/// slot and current name, the new name being sanitized. Empty slots
+/// are skipped.
+pub fn rename_bank(bank: &mut [Instrument], mut f: impl FnMut(usize, &str) -> String) {
+    for (slot, instr) in bank.iter_mut().enumerate() {
+        let Some(old) = instr.name() else {
+            continue;
+        };
+
+        let name = sanitize_name(&f(slot, old));
+        instr.set_name(name);
+    }
+}
+
+/// Slots of the instruments of the bank referencing the given EQ.
+/// Instruments without EQ (MIDI out, empty slots) are skipped.
+pub fn instruments_using_eq(bank: &[Instrument], eq: u8) -> Vec<usize> {
+    bank.iter()
+        .enumerate()
+        .filter(|(_, instr)| instr.equ() == Some(eq))
+        .map(|(slot, _)| slot)
+        .collect()
+}
+
+/// Instruments of the bank with their slot, sorted by name ignoring
+/// case, empty slots last. Equal names are kept in slot order.
+pub fn sorted_by_name(bank: &[Instrument]) -> Vec<(usize, &Instrument)> {
+    let mut sorted: Vec<(usize, &Instrument)> = bank.iter().enumerate().collect();
+    sorted.sort_by_cached_key(|(slot, instr)| {
+        (
+            instr.is_empty(),
+            instr.name().unwrap_or("").to_lowercase(),
+            *slot,
+        )
+    });
+    sorted
 }
 
 impl Instrument {
     pub const INSTRUMENT_MEMORY_SIZE: usize = 215;
     pub const V4_SIZE: usize = Self::INSTRUMENT_MEMORY_SIZE;
 
+    /// Transpose (and EQ) byte of a slot, after the kind and the name
+    const TRANSP_EQ_OFFSET: usize = 1 + INSTRUMENT_NAME_LENGTH;
+    const TABLE_TICK_OFFSET: usize = Self::TRANSP_EQ_OFFSET + 1;
+
+    /// Distance in bytes between two consecutive instrument slots of a
+    /// song bank for the given firmware version.
+    pub fn bank_stride(_ver: Version) -> usize {
+        Self::INSTRUMENT_MEMORY_SIZE
+    }
+
+    /// Content of an empty slot
+    pub fn empty() -> Self {
+        Instrument::None
+    }
+
     pub fn is_empty(&self) -> bool {
         match self {
             Instrument::None => true,
@@ -180,6 +338,96 @@ impl Instrument {
         }
     }
 
+    /// Same as [`Instrument::is_empty`]
+    pub fn is_none(&self) -> bool {
+        self.is_empty()
+    }
+
+    /// Tell if the slot holds an actual instrument
+    pub fn is_some(&self) -> bool {
+        !self.is_empty()
+    }
+
+    /// Instrument playing samples from the SD card. The WavSynth plays
+    /// built-in single cycle waves and is not considered sample based.
+    pub fn is_sample_based(&self) -> bool {
+        matches!(self, Instrument::Sampler(_))
+    }
+
+    /// Instrument driving external gear over MIDI
+    pub fn is_midi(&self) -> bool {
+        matches!(self, Instrument::MIDIOut(_) | Instrument::External(_))
+    }
+
+    /// Instrument generating its sound inside the M8
+    pub fn is_internal_synth(&self) -> bool {
+        matches!(
+            self,
+            Instrument::WavSynth(_)
+                | Instrument::MacroSynth(_)
+                | Instrument::FMSynth(_)
+                | Instrument::HyperSynth(_)
+        )
+    }
+
+    /// Tell if the instrument is still in the state the M8 gives to a
+    /// newly created instrument of the same kind. The instrument number
+    /// and associated EQ are ignored, as they are allocated by the device.
+    pub fn is_default(&self, ver: Version) -> bool {
+        let mut reference = match self {
+            Instrument::WavSynth(ws) => Instrument::WavSynth(WavSynth {
+                number: ws.number,
+                ..WavSynth::default_for(ver)
+            }),
+            Instrument::MacroSynth(ms) => Instrument::MacroSynth(MacroSynth {
+                number: ms.number,
+                ..MacroSynth::default_for(ver)
+            }),
+            Instrument::Sampler(s) => Instrument::Sampler(Sampler {
+                number: s.number,
+                ..Sampler::default_for(ver)
+            }),
+            Instrument::MIDIOut(mo) => Instrument::MIDIOut(MIDIOut {
+                number: mo.number,
+                reserved: mo.reserved,
+                ..MIDIOut::default_for(ver)
+            }),
+            Instrument::FMSynth(fs) => Instrument::FMSynth(FMSynth {
+                number: fs.number,
+                ..FMSynth::default_for(ver)
+            }),
+            Instrument::HyperSynth(hs) => Instrument::HyperSynth(HyperSynth {
+                number: hs.number,
+                ..HyperSynth::default_for(ver)
+            }),
+            Instrument::External(ex) => Instrument::External(ExternalInst {
+                number: ex.number,
+                ..ExternalInst::default_for(ver)
+            }),
+            Instrument::None => return true,
+        };
+
+        if let Some(eq) = self.equ() {
+            reference.set_eq(eq);
+        }
+
+        if let (Some(params), Some(ref_params)) =
+            (self.synth_params(), reference.synth_params_mut())
+        {
+            ref_params.mod_padding = params.mod_padding.clone();
+        }
+
+        *self == reference
+    }
+
+    /// Tell if the instrument has a FIN (fine tune) command. Its place
+    /// in the command list depends on the instrument, and External
+    /// instruments have MIDI commands (MPB, MPG) instead, so look it
+    /// up with [`CommandPack::find_command`].
+    pub fn has_fine_tune_command(&self, ver: Version) -> bool {
+        self.instr_command_text(ver).find_command("FIN").is_some()
+    }
+
     pub fn instr_command_text(&self, ver: Version) -> CommandPack {
         let (commands, mods) = match self {
             Instrument::WavSynth(ws) => (ws.command_name(ver), &ws.synth_params.mods),
@@ -203,6 +451,226 @@ impl Instrument {
         }
     }
 
+    /// Call the visitor callback matching the instrument kind.
+    pub fn accept<V: InstrumentVisitor>(&self, slot: usize, visitor: &mut V) {
+        match self {
+            Instrument::WavSynth(ws) => visitor.visit_wavsynth(slot, ws),
+            Instrument::MacroSynth(ms) => visitor.visit_macrosynth(slot, ms),
+            Instrument::Sampler(s) => visitor.visit_sampler(slot, s),
+            Instrument::MIDIOut(mo) => visitor.visit_midi_out(slot, mo),
+            Instrument::FMSynth(fs) => visitor.visit_fm(slot, fs),
+            Instrument::HyperSynth(hs) => visitor.visit_hypersynth(slot, hs),
+            Instrument::External(ex) => visitor.visit_external(slot, ex),
+            Instrument::None => visitor.visit_empty(slot),
+        }
+    }
+
+    /// Parameters shared by all the instruments, including the modulators.
+    pub fn synth_params(&self) -> Option<&SynthParams> {
+        match self {
+            Instrument::WavSynth(ws) => Some(&ws.synth_params),
+            Instrument::MacroSynth(ms) => Some(&ms.synth_params),
+            Instrument::Sampler(s) => Some(&s.synth_params),
+            Instrument::MIDIOut(mo) => Some(&mo.mods),
+            Instrument::FMSynth(fs) => Some(&fs.synth_params),
+            Instrument::HyperSynth(hs) => Some(&hs.synth_params),
+            Instrument::External(ex) => Some(&ex.synth_params),
+            Instrument::None => None,
+        }
+    }
+
+    pub fn synth_params_mut(&mut self) -> Option<&mut SynthParams> {
+        match self {
+            Instrument::WavSynth(ws) => Some(&mut ws.synth_params),
+            Instrument::MacroSynth(ms) => Some(&mut ms.synth_params),
+            Instrument::Sampler(s) => Some(&mut s.synth_params),
+            Instrument::MIDIOut(mo) => Some(&mut mo.mods),
+            Instrument::FMSynth(fs) => Some(&mut fs.synth_params),
+            Instrument::HyperSynth(hs) => Some(&mut hs.synth_params),
+            Instrument::External(ex) => Some(&mut ex.synth_params),
+            Instrument::None => None,
+        }
+    }
+
+    /// Bring the instrument in a canonical form, so that two instruments
+    /// sounding the same compare equal and are written identically.
+    /// Modulators with an OFF destination keep their kind but have their
+    /// parameters zeroed.
+    pub fn normalize(&mut self) {
+        if let Some(params) = self.synth_params_mut() {
+            params.normalize_mods();
+            params.mod_padding.clear();
+        }
+    }
+
+    /// `Debug` rendering with every integer field in two digit
+    /// hexadecimal, as displayed by the M8 (signed values in two's
+    /// complement, like the device). Names and strings are kept as is.
+    /// Pretty printing is not available, it would prefix the numbers
+    /// with `0x`.
+    pub fn debug_hex(&self) -> String {
+        format!("{:02X?}", self)
+    }
+
+    /// Hash of the normalized instrument, equal for two instruments
+    /// sounding the same even if their bytes differ in unused fields.
+    /// The hash (FNV-1a of the written instrument) is stable across
+    /// runs and builds.
+    pub fn content_hash(&self, ver: Version) -> u64 {
+        let mut normalized = self.clone();
+        normalized.normalize();
+
+        let mut w = Writer::new(vec![0; Instrument::INSTRUMENT_MEMORY_SIZE]);
+        normalized.write(ver, &mut w);
+        fnv1a(FNV_OFFSET, w.as_slice())
+    }
+
+    /// Kinds of modulator selectable in a modulator slot. Before
+    /// firmware 3.0 the slots were fixed (two AHD envelopes then two
+    /// LFOs), any kind can be used in any slot since.
+    pub fn allowed_mod_types(&self, slot: usize, ver: Version) -> &'static [ModKind] {
+        if self.is_empty() || slot >= SynthParams::MODULATOR_COUNT {
+            &[]
+        } else if ver.at_least(3, 0) {
+            &ModKind::ALL
+        } else if slot < 2 {
+            &[ModKind::AHDEnv]
+        } else {
+            &[ModKind::LFO]
+        }
+    }
+
+    /// Oldest firmware able to load the instrument, given the features
+    /// it actually uses: the synth kind, modulators outside of the
+    /// pre 3.0 fixed slots, an associated EQ and extended FM waves.
+    pub fn min_version(&self) -> Version {
+        let at = |major, minor| Version {
+            major,
+            minor,
+            patch: 0,
+        };
+
+        let mut min = match self {
+            Instrument::HyperSynth(_) | Instrument::External(_) => at(3, 0),
+            _ => at(2, 0),
+        };
+
+        if let Some(params) = self.synth_params() {
+            let fixed_slots = params
+                .mods
+                .iter()
+                .enumerate()
+                .all(|(i, m)| m.kind() == if i < 2 { ModKind::AHDEnv } else { ModKind::LFO });
+            if !fixed_slots {
+                min = at(3, 0);
+            }
+        }
+
+        if let Some(eq) = self.equ() {
+            if (eq as usize) < crate::V4_OFFSETS.instrument_eq_count {
+                min = at(4, 0);
+            }
+        }
+
+        if let Instrument::FMSynth(fm) = self {
+            let extended = fm.operators.iter().any(|op| op.shape.is_extended());
+            if extended {
+                min = at(4, 1);
+            }
+        }
+
+        min
+    }
+
+    /// Filter types selectable on the instrument, empty for the ones
+    /// without filter.
+    pub fn filter_types(&self, ver: Version) -> &'static [&'static str] {
+        match self {
+            Instrument::WavSynth(ws) => ws.filter_types(ver),
+            Instrument::MacroSynth(ms) => ms.filter_types(ver),
+            Instrument::Sampler(s) => s.filter_types(ver),
+            Instrument::FMSynth(fs) => fs.filter_types(ver),
+            Instrument::HyperSynth(hs) => hs.filter_types(ver),
+            Instrument::External(ex) => ex.filter_types(ver),
+            Instrument::MIDIOut(_) | Instrument::None => &[],
+        }
+    }
+
+    /// Filter name, cutoff and resonance, see [`SynthParams::describe_filter`]
+    pub fn describe_filter(&self, ver: Version) -> Option<String> {
+        let types = self.filter_types(ver);
+        if types.is_empty() {
+            return None;
+        }
+        self.synth_params().map(|p| p.describe_filter(types))
+    }
+
+    /// Names of the modulation destinations of the instrument
+    pub fn destination_names(&self, ver: Version) -> &'static [&'static str] {
+        match self {
+            Instrument::WavSynth(ws) => ws.destination_names(ver),
+            Instrument::MacroSynth(ms) => ms.destination_names(ver),
+            Instrument::Sampler(s) => s.destination_names(ver),
+            Instrument::MIDIOut(mo) => mo.destination_names(ver),
+            Instrument::FMSynth(fs) => fs.destination_names(ver),
+            Instrument::HyperSynth(hs) => hs.destination_names(ver),
+            Instrument::External(ex) => ex.destination_names(ver),
+            Instrument::None => &[],
+        }
+    }
+
+    /// Check the instrument for settings meaningless for its kind.
+    pub fn validate(&self, ver: Version) -> Vec<InstrumentWarning> {
+        let Some(params) = self.synth_params() else {
+            return vec![];
+        };
+        let dests = self.destination_names(ver);
+
+        let mut warnings: Vec<InstrumentWarning> = params
+            .mods
+            .iter()
+            .enumerate()
+            .filter(|(_, m)| m.dest() as usize >= dests.len())
+            .map(|(slot, m)| InstrumentWarning::UnknownModDestination {
+                slot,
+                dest: m.dest(),
+            })
+            .collect();
+
+        if let Instrument::MIDIOut(mo) = self {
+            if mo.midi_channel().is_none() {
+                warnings.push(InstrumentWarning::InvalidMidiChannel {
+                    channel: mo.channel,
+                });
+            }
+        }
+
+        warnings
+    }
+
+    /// List all the active modulations (destination not OFF) of the
+    /// instrument, with destination names resolved for this instrument.
+    pub fn modulation_table(&self, ver: Version) -> Vec<ModRow> {
+        let Some(params) = self.synth_params() else {
+            return vec![];
+        };
+        let dests = self.destination_names(ver);
+
+        params
+            .mods
+            .iter()
+            .enumerate()
+            .filter(|(_, m)| m.dest() != 0)
+            .map(|(slot, m)| ModRow {
+                slot,
+                mod_type: m.type_name(),
+                destination: lookup(dests, m.dest()),
+                amount: (m.amount() as i16 - 0x80) as i8,
+                params: m.params(),
+            })
+            .collect()
+    }
+
     pub fn write(&self, ver: Version, w: &mut Writer) {
         match self {
             Instrument::WavSynth(ws) => {
@@ -237,16 +705,104 @@ impl Instrument {
         }
     }
 
+    /// Fill a whole empty slot, for writers not starting from the
+    /// bytes of an existing file ([`Instrument::write`] only marks the
+    /// slot empty, keeping the other bytes as read). The layout is the
+    /// one of the empty slots of a new project (DEFAULT.m8s): 0xFF up
+    /// to the sample path, except the transpose and table tick set to
+    /// 1, the remaining bytes being zeroes.
+    pub fn write_empty_slot(w: &mut Writer) {
+        let mut bytes = [0; Instrument::INSTRUMENT_MEMORY_SIZE];
+        bytes[..Sampler::SAMPLE_PATH_OFFSET].fill(0xFF);
+
+        bytes[Instrument::TRANSP_EQ_OFFSET] = 1;
+        bytes[Instrument::TABLE_TICK_OFFSET] = 1;
+        w.write_bytes(&bytes);
+    }
+
+    /// Name of the instrument without the trailing spaces used by the
+    /// device as padding, None only for an empty slot. An unnamed
+    /// instrument gives an empty name.
     pub fn name(&self) -> Option<&str> {
+        let name = match self {
+            Instrument::WavSynth(ws) => &ws.name,
+            Instrument::MacroSynth(ms) => &ms.name,
+            Instrument::Sampler(s) => &s.name,
+            Instrument::MIDIOut(mo) => &mo.name,
+            Instrument::FMSynth(fs) => &fs.name,
+            Instrument::HyperSynth(hs) => &hs.name,
+            Instrument::External(ex) => &ex.name,
+            Instrument::None => return None,
+        };
+
+        Some(name.trim_end_matches(' '))
+    }
+
+    /// Compact one line description of the instrument for list views:
+    /// slot, kind, name padded to 12 characters then the most salient
+    /// parameters of the kind, e.g. `05 FM   BASS01        ALG:[A>B]+[C>D]  CUT:40`
+    pub fn summary(&self, _ver: Version) -> String {
+        let cut = |p: &SynthParams| format!("CUT:{:02X}", p.filter_cutoff);
+        let (number, kind, name, detail) = match self {
+            Instrument::WavSynth(ws) => (
+                ws.number,
+                "WAV",
+                &ws.name,
+                format!("SHP:{:?}  {}", ws.shape, cut(&ws.synth_params)),
+            ),
+            Instrument::MacroSynth(ms) => (
+                ms.number,
+                "MAC",
+                &ms.name,
+                format!("SHP:{:?}  {}", ms.shape, cut(&ms.synth_params)),
+            ),
+            Instrument::Sampler(s) => {
+                let file = s.sample_path.rsplit('/').next().unwrap_or("");
+                let detail = format!("SMP:{}  {}", file, cut(&s.synth_params));
+                (s.number, "SMP", &s.name, detail)
+            }
+            Instrument::MIDIOut(mo) => (
+                mo.number,
+                "MID",
+                &mo.name,
+                format!("PRT:{:02X}  CH:{:02X}", mo.port, mo.channel),
+            ),
+            Instrument::FMSynth(fs) => (
+                fs.number,
+                "FM",
+                &fs.name,
+                format!("ALG:{}  {}", fs.algo.str(), cut(&fs.synth_params)),
+            ),
+            Instrument::HyperSynth(hs) => (
+                hs.number,
+                "HYP",
+                &hs.name,
+                format!("SWM:{:02X}  {}", hs.swarm, cut(&hs.synth_params)),
+            ),
+            Instrument::External(ex) => (
+                ex.number,
+                "EXT",
+                &ex.name,
+                format!("PRT:{:02X}  CH:{:02X}", ex.port, ex.channel),
+            ),
+            Instrument::None => return String::from("-- ---"),
+        };
+
+        format!("{:02X} {:<4} {:<12}  {}", number, kind, name, detail)
+    }
+
+    /// Tell if the notes played by the instrument follow the chain and
+    /// song transpose.
+    pub fn transpose(&self) -> bool {
         match self {
-            Instrument::WavSynth(ws) => Some(&ws.name),
-            Instrument::MacroSynth(ms) => Some(&ms.name),
-            Instrument::Sampler(s) => Some(&s.name),
-            Instrument::MIDIOut(_) => None,
-            Instrument::FMSynth(fs) => Some(&fs.name),
-            Instrument::HyperSynth(hs) => Some(&hs.name),
-            Instrument::External(ex) => Some(&ex.name),
-            Instrument::None => None,
+            Instrument::WavSynth(ws) => ws.transpose,
+            Instrument::MacroSynth(ms) => ms.transpose,
+            Instrument::Sampler(s) => s.transpose,
+            Instrument::MIDIOut(mo) => mo.transpose,
+            Instrument::FMSynth(fs) => fs.transpose,
+            Instrument::HyperSynth(hs) => hs.transpose,
+            Instrument::External(ex) => ex.transpose,
+            Instrument::None => false,
         }
     }
 
@@ -293,14 +849,21 @@ impl Instrument {
     pub fn read_from_reader(reader: &mut Reader) -> M8Result<InstrumentWithEq> {
         let instrument_end_offset = Instrument::INSTRUMENT_MEMORY_SIZE + Version::SIZE;
         if reader.len() < instrument_end_offset {
-            return Err(ParseError(
-                "File is not long enough to be a M8 Instrument".to_string(),
+            return Err(ParseError::new(
+                ParseErrorKind::TooShort,
+                "File is not long enough to be a M8 Instrument",
             ));
         }
 
-        let version = Version::from_reader(reader)?;
+        let version = read_header(reader)?.version;
         let instrument = Self::from_reader(reader, 0, version)?;
 
+        let table = if reader.len() >= instrument_end_offset + Table::V4_SIZE {
+            Some(Table::from_reader(reader, version)?)
+        } else {
+            None
+        };
+
         let eq = match V4_1_OFFSETS.instrument_file_eq_offset {
             None => None,
             Some(ofs) if version.at_least(4, 0) => {
@@ -314,7 +877,11 @@ impl Instrument {
             Some(_) => None,
         };
 
-        Ok(InstrumentWithEq { instrument, eq })
+        Ok(InstrumentWithEq {
+            instrument,
+            eq,
+            table,
+        })
     }
 
     /// Read a M8 instrument file along with its optional Eq definition.
@@ -326,8 +893,87 @@ impl Instrument {
         Self::read_from_reader(&mut reader)
     }
 
+    /// Content of an instrument file (.m8i) loadable by the device:
+    /// header, instrument memory, then an empty instrument table. From
+    /// 4.1 the EQ used by the instrument, if any, is stored after the
+    /// table, making the file longer.
+    pub fn write_m8i(&self, ver: Version, eq: Option<&Equ>) -> Vec<u8> {
+        let table_end = Version::SIZE + Instrument::INSTRUMENT_MEMORY_SIZE + Table::V4_SIZE;
+        let eq = match (eq, V4_1_OFFSETS.instrument_file_eq_offset) {
+            (Some(eq), Some(ofs)) if ver.at_least(4, 1) => Some((eq, ofs)),
+            _ => None,
+        };
+        let size = eq.map_or(table_end, |(_, ofs)| ofs + Equ::V4_SIZE);
+
+        let mut w = Writer::new(vec![0; size]);
+        Header::for_instrument(ver).write(&mut w);
+        self.write(ver, &mut w);
+
+        w.seek(Version::SIZE + Instrument::INSTRUMENT_MEMORY_SIZE);
+        Table::empty(ver).write(&mut w);
+
+        if let Some((eq, ofs)) = eq {
+            w.seek(ofs);
+            eq.write(&mut w);
+        }
+        w.finish()
+    }
+
+    /// Read a single instrument stored at `offset` (e.g. a slot of the
+    /// instrument bank of a song file), without loading the whole file.
+    pub fn read_at(
+        reader: &mut (impl std::io::Read + std::io::Seek),
+        offset: u64,
+        ver: Version,
+    ) -> M8Result<Self> {
+        let io_error = |e: std::io::Error| {
+            let kind = if e.kind() == std::io::ErrorKind::UnexpectedEof {
+                ParseErrorKind::UnexpectedEof
+            } else {
+                ParseErrorKind::Io
+            };
+            ParseError::new(
+                kind,
+                format!("Could not read instrument at {:#x}: {}", offset, e),
+            )
+        };
+
+        let mut buf = vec![0; Instrument::bank_stride(ver)];
+        reader
+            .seek(std::io::SeekFrom::Start(offset))
+            .map_err(io_error)?;
+        reader.read_exact(&mut buf).map_err(io_error)?;
+
+        Self::from_reader(&mut Reader::new(buf), 0, ver)
+    }
+
+    /// Parse an instrument at the current position of the reader, without
+    /// any version header: containers holding several instruments (like
+    /// the bank of a song) read their version once and share it. The
+    /// reader is left at the start of the next instrument.
     pub fn from_reader(reader: &mut Reader, number: u8, version: Version) -> M8Result<Self> {
+        // V1 files use yet another layout, refuse them instead of mis-parsing
+        // them as V2 instruments.
+        if !version.at_least(2, 0) {
+            return Err(ParseError::new(
+                ParseErrorKind::UnsupportedVersion,
+                format!("V1 files unsupported (version {})", version),
+            ));
+        }
+
         let start_pos = reader.pos();
+        let stride = Instrument::bank_stride(version);
+        if reader.len() < start_pos + stride {
+            return Err(ParseError::new(
+                ParseErrorKind::TooShort,
+                format!(
+                    "Truncated instrument, {} bytes left out of {}",
+                    reader.len().saturating_sub(start_pos),
+                    stride
+                ),
+            ));
+        }
+
         let kind = reader.read();
 
         let instr = match kind {
@@ -346,15 +992,953 @@ impl Instrument {
             }
             0xFF => Self::None,
             _ => {
-                return Err(ParseError(format!(
-                    "Instrument type {} not supported",
-                    kind
-                )))
+                return Err(ParseError::new(
+                    ParseErrorKind::UnsupportedInstrument(kind),
+                    format!("Instrument type {} not supported", kind),
+                ))
             }
         };
 
-        reader.set_pos(start_pos + Instrument::INSTRUMENT_MEMORY_SIZE);
+        reader.resync(start_pos + stride, "Instrument");
 
         Ok(instr)
     }
 }
+
+/// Parse an instrument file (.m8i) from untrusted bytes, as a fuzzing
+/// entry point. The reads themselves are not checked: the file length
+/// is checked once against a whole instrument (and again before the
+/// optional table and EQ), every instrument layout fitting in it, and
+/// invalid values give errors. No panic was found on random inputs,
+/// but it is not proven.
+pub fn parse_instrument_nopanic(bytes: &[u8]) -> M8Result<Instrument> {
+    Instrument::try_from(bytes)
+}
+
+impl TryFrom<&[u8]> for Instrument {
+    type Error = ParseError;
+
+    /// Parse the content of a whole instrument file (.m8i)
+    fn try_from(bytes: &[u8]) -> M8Result<Self> {
+        let mut reader = Reader::new(bytes.to_vec());
+        Ok(Instrument::read_from_reader(&mut reader)?.instrument)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::instruments::*;
+    use crate::songs::Song;
+    use crate::FX;
+
+    fn test_file() -> Song {
+        let mut f = std::fs::File::open("./examples/songs/TEST-FILE.m8s")
+            .expect("Could not open TEST-FILE");
+        Song::read(&mut f).expect("Could not parse TEST-FILE")
+    }
+
+    fn instrument_file(major: u8, minor: u8, kind: u8) -> Vec<u8> {
+        let mut buf = b"M8VERSION\0".to_vec();
+        buf.extend([minor << 4, major, 0, 0]);
+        buf.push(kind);
+        buf.resize(Version::SIZE + Instrument::INSTRUMENT_MEMORY_SIZE, 0);
+        buf
+    }
+
+    #[test]
+    fn v1_instrument_is_rejected() {
+        let mut reader = Reader::new(instrument_file(1, 4, 0x00));
+        match Instrument::read_from_reader(&mut reader) {
+            Err(err) => {
+                assert_eq!(err.kind(), ParseErrorKind::UnsupportedVersion);
+                assert!(err.message().contains("V1 files unsupported"))
+            }
+            Ok(_) => panic!("V1 instrument should not parse"),
+        }
+    }
+
+    #[test]
+    fn unsupported_instrument_kind_is_typed() {
+        let mut reader = Reader::new(instrument_file(4, 0, 0x42));
+        match Instrument::read_from_reader(&mut reader) {
+            Err(err) => {
+                assert_eq!(err.kind(), ParseErrorKind::UnsupportedInstrument(0x42));
+                assert_eq!(
+                    err.to_string(),
+                    "ParseError: Instrument type 66 not supported"
+                );
+            }
+            Ok(_) => panic!("Unknown instrument kind should not parse"),
+        }
+    }
+
+    #[test]
+    fn full_length_name_is_kept() {
+        let mut file = instrument_file(4, 0, 0x00);
+        file[Version::SIZE + 1..Version::SIZE + 13].copy_from_slice(b"TWELVE CHARS");
+
+        let mut reader = Reader::new(file);
+        let instr = Instrument::read_from_reader(&mut reader)
+            .unwrap()
+            .instrument;
+        assert_eq!(instr.name(), Some("TWELVE CHARS"));
+    }
+
+    #[test]
+    fn empty_and_padded_names() {
+        let named = |name: &[u8]| {
+            let mut file = instrument_file(4, 0, 0x00);
+            file[Version::SIZE + 1..Version::SIZE + 13].copy_from_slice(name);
+            Instrument::try_from(&file[..]).unwrap()
+        };
+
+        assert_eq!(named(&[0; 12]).name(), Some(""));
+        assert_eq!(named(b"AB          ").name(), Some("AB"));
+        assert_eq!(named(b"A B\0\0\0\0\0\0\0\0\0").name(), Some("A B"));
+        assert_eq!(Instrument::None.name(), None);
+    }
+
+    #[test]
+    fn fm_modulation_table() {
+        let song = test_file();
+        let mut instr = song.instruments[4].clone();
+        let Instrument::FMSynth(fm) = &mut instr else {
+            panic!("Instrument 4 should be a FMSynth")
+        };
+
+        fm.synth_params.mods = [
+            LFO {
+                shape: LfoShape::SIN,
+                dest: 7,
+                trigger_mode: LfoTriggerMode::FREE,
+                freq: 0x10,
+                amount: 0xC0,
+                retrigger: 0,
+            }
+            .to_mod(),
+            AHDEnv {
+                dest: 2,
+                amount: 0x40,
+                ..Default::default()
+            }
+            .to_mod(),
+            AHDEnv::default().to_mod(),
+            AHDEnv::default().to_mod(),
+        ];
+
+        let rows = instr.modulation_table(song.version);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].slot, 0);
+        assert_eq!(rows[0].mod_type, "LFO");
+        assert_eq!(rows[0].destination, dests::CUTOFF);
+        assert_eq!(rows[0].amount, 0x40);
+        assert_eq!(rows[1].slot, 1);
+        assert_eq!(rows[1].mod_type, "AHD ENV");
+        assert_eq!(rows[1].destination, dests::PITCH);
+        assert_eq!(rows[1].amount, -0x40);
+    }
+
+    #[test]
+    fn visitor_counts_fm_synths() {
+        struct FmCounter(Vec<usize>);
+
+        impl InstrumentVisitor for FmCounter {
+            fn visit_fm(&mut self, slot: usize, _instr: &FMSynth) {
+                self.0.push(slot)
+            }
+        }
+
+        let song = test_file();
+        let mut counter = FmCounter(vec![]);
+        visit_bank(&song.instruments[0..8], &mut counter);
+        assert_eq!(counter.0, vec![4]);
+    }
+
+    #[test]
+    fn m8_default_matches_fresh_instrument() {
+        let mut f = std::fs::File::open("./examples/songs/CommandMappingV4/CMDMAPPING_4_0.m8s")
+            .expect("Could not open CMDMAPPING_4_0");
+        let song = Song::read(&mut f).expect("Could not parse CMDMAPPING_4_0");
+
+        // Untouched instruments created on the device
+        let Instrument::MacroSynth(ms) = &song.instruments[0x70] else {
+            panic!("Instrument 0x70 should be a MacroSynth")
+        };
+        let Instrument::WavSynth(ws) = &song.instruments[0x71] else {
+            panic!("Instrument 0x71 should be a WavSynth")
+        };
+
+        for params in [&ms.synth_params, &ws.synth_params] {
+            let mut expected = SynthParams::m8_default();
+            expected.set_eq(params.associated_eq);
+            expected.mod_padding = params.mod_padding.clone();
+            assert_eq!(params, &expected);
+        }
+
+        assert!(song.instruments[0x70].is_default(song.version));
+        assert!(song.instruments[0x71].is_default(song.version));
+    }
+
+    #[test]
+    fn fresh_wavsynth_is_default() {
+        let ver = Version {
+            major: 4,
+            minor: 0,
+            patch: 1,
+        };
+        let mut instr = Instrument::WavSynth(WavSynth {
+            number: 0x12,
+            ..WavSynth::default_for(ver)
+        });
+        assert!(instr.is_default(ver));
+
+        instr.set_name("LEAD".to_string());
+        assert!(!instr.is_default(ver));
+    }
+
+    #[test]
+    fn normalized_off_mods_write_identically() {
+        let ver = Version {
+            major: 4,
+            minor: 0,
+            patch: 1,
+        };
+
+        let mut a = Instrument::WavSynth(WavSynth::default_for(ver));
+        let mut b = a.clone();
+        a.synth_params_mut().unwrap().mods[2] = LFO {
+            shape: LfoShape::RANDOM,
+            dest: 0,
+            trigger_mode: LfoTriggerMode::RETRIG,
+            freq: 0x42,
+            amount: 0x12,
+            retrigger: 0,
+        }
+        .to_mod();
+        b.synth_params_mut().unwrap().mods[2] = LFO {
+            shape: LfoShape::SIN,
+            dest: 0,
+            trigger_mode: LfoTriggerMode::FREE,
+            freq: 0x10,
+            amount: 0xFF,
+            retrigger: 0x20,
+        }
+        .to_mod();
+        assert_ne!(a, b);
+
+        a.normalize();
+        b.normalize();
+        assert_eq!(a, b);
+
+        let write = |instr: &Instrument| {
+            let mut w = Writer::new(vec![0; Instrument::INSTRUMENT_MEMORY_SIZE]);
+            instr.write(ver, &mut w);
+            w.finish()
+        };
+        assert_eq!(write(&a), write(&b));
+        assert_eq!(a.synth_params().unwrap().mods[2].kind(), ModKind::LFO);
+    }
+
+    #[test]
+    fn every_synth_allows_mod_types() {
+        let ver = Version::default();
+        let bank = [
+            Instrument::WavSynth(WavSynth::default_for(ver)),
+            Instrument::MacroSynth(MacroSynth::default_for(ver)),
+            Instrument::Sampler(Sampler::default_for(ver)),
+            Instrument::MIDIOut(MIDIOut::default_for(ver)),
+            Instrument::FMSynth(FMSynth::default_for(ver)),
+            Instrument::HyperSynth(HyperSynth::default_for(ver)),
+            Instrument::External(ExternalInst::default_for(ver)),
+        ];
+
+        for instr in &bank {
+            for slot in 0..SynthParams::MODULATOR_COUNT {
+                let allowed = instr.allowed_mod_types(slot, ver);
+                assert!(!allowed.is_empty());
+                let current = instr.synth_params().unwrap().mods[slot].kind();
+                assert!(allowed.contains(&current));
+            }
+            assert!(instr.allowed_mod_types(4, ver).is_empty());
+        }
+        assert!(Instrument::None.allowed_mod_types(0, ver).is_empty());
+    }
+
+    #[test]
+    fn sample_path_ends_the_instrument_memory() {
+        assert_eq!(
+            Sampler::SAMPLE_PATH_OFFSET + Sampler::SAMPLE_PATH_LENGTH,
+            Instrument::INSTRUMENT_MEMORY_SIZE
+        );
+
+        let ver = Version::default();
+        let mut smp = Sampler::default_for(ver);
+        smp.sample_path = "/Samples/".to_string() + &"K".repeat(119);
+        let bytes = Instrument::Sampler(smp.clone()).write_m8i(ver, None);
+
+        match Instrument::try_from(&bytes[..]).unwrap() {
+            Instrument::Sampler(parsed) => assert_eq!(parsed.sample_path, smp.sample_path),
+            other => panic!("Expected a sampler, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn consecutive_instruments_share_a_version() {
+        let ver = Version::default();
+        let first = Instrument::WavSynth(WavSynth::default_for(ver));
+        let second = Instrument::MacroSynth(MacroSynth::default_for(ver));
+
+        let stride = Instrument::bank_stride(ver);
+        let mut w = Writer::new(vec![0; 2 * stride]);
+        first.write(ver, &mut w);
+        w.seek(stride);
+        second.write(ver, &mut w);
+
+        let mut reader = Reader::new(w.finish());
+        let a = Instrument::from_reader(&mut reader, 0, ver).unwrap();
+        let b = Instrument::from_reader(&mut reader, 1, ver).unwrap();
+        assert_eq!(reader.pos(), 2 * stride);
+        assert!(matches!(a, Instrument::WavSynth(_)));
+        assert!(matches!(
+            b,
+            Instrument::MacroSynth(MacroSynth { number: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn debug_hex_matches_the_device() {
+        let mut wav = WavSynth::default_for(Version::default());
+        wav.name = "KICK 10".to_string();
+        wav.synth_params.filter_cutoff = 0x40;
+
+        wav.synth_params.mods[0] = AHDEnv {
+            dest: 1,
+            amount: 0xFF,
+            ..Default::default()
+        }
+        .to_mod();
+
+        let dbg = Instrument::WavSynth(wav).debug_hex();
+        assert!(dbg.contains("filter_cutoff: 40,"));
+        assert!(dbg.contains("mixer_pan: 80,"));
+        assert!(dbg.contains("dest: 01, amount: FF,"));
+        assert!(dbg.contains("\"KICK 10\""));
+        assert!(dbg.contains("shape: PULSE12"));
+    }
+
+    #[test]
+    fn min_version_follows_used_features() {
+        let ver = Version::default();
+        let wav = Instrument::WavSynth(WavSynth::default_for(ver));
+        assert!(!wav.min_version().at_least(3, 0));
+
+        let hyper = Instrument::HyperSynth(HyperSynth::default_for(ver));
+        assert!(hyper.min_version().at_least(3, 0));
+
+        let mut fm = FMSynth::default_for(ver);
+        fm.operators[2].shape = FMWave::try_from(0x20).unwrap();
+        assert!(Instrument::FMSynth(fm).min_version().at_least(4, 1));
+    }
+
+    #[test]
+    fn instrument_categories() {
+        let ver = Version::default();
+        let categories = |i: &Instrument| (i.is_sample_based(), i.is_midi(), i.is_internal_synth());
+
+        let internal = (false, false, true);
+        assert_eq!(
+            categories(&Instrument::WavSynth(WavSynth::default_for(ver))),
+            internal
+        );
+        assert_eq!(
+            categories(&Instrument::MacroSynth(MacroSynth::default_for(ver))),
+            internal
+        );
+        assert_eq!(
+            categories(&Instrument::FMSynth(FMSynth::default_for(ver))),
+            internal
+        );
+        assert_eq!(
+            categories(&Instrument::HyperSynth(HyperSynth::default_for(ver))),
+            internal
+        );
+
+        let midi = (false, true, false);
+        assert_eq!(
+            categories(&Instrument::MIDIOut(MIDIOut::default_for(ver))),
+            midi
+        );
+        assert_eq!(
+            categories(&Instrument::External(ExternalInst::default_for(ver))),
+            midi
+        );
+
+        let sampler = Instrument::Sampler(Sampler::default_for(ver));
+        assert_eq!(categories(&sampler), (true, false, false));
+        assert_eq!(categories(&Instrument::None), (false, false, false));
+    }
+
+    #[test]
+    fn mod_destination_missing_from_synth_warns() {
+        let ver = Version::default();
+        let binv = 14;
+        assert_eq!(
+            Instrument::HyperSynth(HyperSynth::default_for(ver)).destination_names(ver)[binv],
+            "MOD BINV"
+        );
+
+        let mut smp = Instrument::Sampler(Sampler::default_for(ver));
+        assert!(smp.validate(ver).is_empty());
+
+        if let Mod::LFO(lfo) = &mut smp.synth_params_mut().unwrap().mods[2] {
+            lfo.dest = binv as u8;
+        }
+        assert_eq!(
+            smp.validate(ver),
+            vec![InstrumentWarning::UnknownModDestination {
+                slot: 2,
+                dest: binv as u8
+            }]
+        );
+    }
+
+    #[test]
+    fn midi_out_channel_zero_warns() {
+        let ver = Version::default();
+        let mut midi = MIDIOut::default_for(ver);
+        assert_eq!(midi.midi_channel(), Some(1));
+        assert_eq!(midi.midi_port().map(MidiPort::name), Some("MIDI + USB"));
+        assert!(Instrument::MIDIOut(midi.clone()).validate(ver).is_empty());
+
+        midi.channel = 0;
+        assert_eq!(midi.midi_channel(), None);
+        assert_eq!(
+            Instrument::MIDIOut(midi).validate(ver),
+            vec![InstrumentWarning::InvalidMidiChannel { channel: 0 }]
+        );
+    }
+
+    #[test]
+    fn empty_instrument_round_trip() {
+        let ver = Version::default();
+        let empty = Instrument::empty();
+        assert!(empty.is_none() && !empty.is_some());
+        assert!(Instrument::WavSynth(WavSynth::default_for(ver)).is_some());
+
+        let mut w = Writer::new(vec![0x42; Instrument::INSTRUMENT_MEMORY_SIZE]);
+        empty.write(ver, &mut w);
+        let bytes = w.finish();
+        assert_eq!(bytes[0], 0xFF);
+        assert_eq!(bytes[1], 0x42);
+        let reread = Instrument::from_reader(&mut Reader::new(bytes), 0, ver).unwrap();
+        assert!(reread.is_none());
+
+        let mut w = Writer::new(vec![0x42; Instrument::INSTRUMENT_MEMORY_SIZE + 1]);
+        Instrument::write_empty_slot(&mut w);
+        assert_eq!(w.pos(), Instrument::INSTRUMENT_MEMORY_SIZE);
+        let bytes = w.finish();
+        assert_eq!(&bytes[12..16], &[0xFF, 0x01, 0x01, 0xFF]);
+        assert_eq!(bytes[Instrument::INSTRUMENT_MEMORY_SIZE - 1], 0);
+        assert_eq!(bytes[Instrument::INSTRUMENT_MEMORY_SIZE], 0x42);
+        let reread = Instrument::from_reader(&mut Reader::new(bytes), 0, ver).unwrap();
+        assert!(reread.is_none());
+    }
+
+    #[test]
+    fn external_has_no_fine_tune_command() {
+        let ver = Version::default();
+        let fm = Instrument::FMSynth(FMSynth::default_for(ver));
+        let ext = Instrument::External(ExternalInst::default_for(ver));
+        assert!(fm.has_fine_tune_command(ver));
+        assert!(!ext.has_fine_tune_command(ver));
+        assert!(!Instrument::None.has_fine_tune_command(ver));
+    }
+
+    #[test]
+    fn filter_description() {
+        let ver = Version::default();
+        let mut wav = WavSynth::default_for(ver);
+        wav.synth_params.filter_type = 1;
+        wav.synth_params.filter_cutoff = 0x40;
+        wav.synth_params.filter_res = 0x10;
+        assert_eq!(
+            Instrument::WavSynth(wav.clone()).describe_filter(ver),
+            Some("LOWPASS 40 10".to_string())
+        );
+
+        wav.synth_params.filter_type = 8;
+        assert_eq!(
+            Instrument::WavSynth(wav).describe_filter(ver).unwrap(),
+            "WAV LP 40 10"
+        );
+
+        let midi = Instrument::MIDIOut(MIDIOut::default_for(ver));
+        assert_eq!(midi.describe_filter(ver), None);
+    }
+
+    #[test]
+    fn sampler_play_mode_classes() {
+        let mut smp = Sampler::default_for(Version::default());
+        let mut classes = vec![];
+        for mode in 0..=SamplePlayMode::BPM_PP as u8 {
+            smp.play_mode = SamplePlayMode::try_from(mode).unwrap();
+            classes.push((smp.is_oscillator_mode(), smp.is_looping()));
+        }
+
+        let (one_shot, looped, osc) = ((false, false), (false, true), (true, true));
+        #[rustfmt::skip]
+        let expected = vec![
+            one_shot, one_shot, looped, looped, looped, looped, // FWD .. REV_PP
+            osc, osc, osc,                                      // OSC .. OSC_PP
+            one_shot, one_shot, looped,                         // REPITCH .. REP_PP
+            one_shot, one_shot, looped,                         // REP_BPM .. BPM_PP
+        ];
+        assert_eq!(classes, expected);
+    }
+
+    #[test]
+    fn sampler_memory_has_no_slice_region() {
+        // kind, name, transpose/eq, table tick, volume, pitch, fine tune,
+        // sample parameters, filter/amp/mixer parameters, then 4 mods
+        let mods_end = 1 + 12 + 5 + 6 + 10 + Sampler::MOD_OFFSET + 4 * 6;
+        assert_eq!(mods_end, Sampler::SAMPLE_PATH_OFFSET);
+        assert_eq!(
+            Sampler::SAMPLE_PATH_OFFSET + 128,
+            Instrument::INSTRUMENT_MEMORY_SIZE
+        );
+    }
+
+    #[test]
+    fn fm_and_sampler_summary() {
+        let ver = Version::default();
+        let mut fm = FMSynth::default_for(ver);
+        fm.number = 5;
+        fm.name = "BASS01".to_string();
+        fm.algo = FmAlgo(7);
+        fm.synth_params.filter_cutoff = 0x40;
+        assert_eq!(
+            Instrument::FMSynth(fm).summary(ver),
+            "05 FM   BASS01        ALG:[A>B]+[C>D]  CUT:40"
+        );
+
+        let mut smp = Sampler::default_for(ver);
+        smp.number = 0x12;
+        smp.name = "KICK".to_string();
+        smp.sample_path = "/Samples/drums/kick.wav".to_string();
+        assert_eq!(
+            Instrument::Sampler(smp).summary(ver),
+            "12 SMP  KICK          SMP:kick.wav  CUT:FF"
+        );
+        assert_eq!(Instrument::None.summary(ver), "-- ---");
+    }
+
+    #[test]
+    fn instrument_file_missing_fields_errors() {
+        // A dump omitting the end of the instrument memory must be
+        // reported, not parsed with zeroed fields.
+        let file = std::fs::read("./examples/instruments/FMDUBSTAB_4_1.m8i")
+            .expect("Could not open FMDUBSTAB_4_1");
+        let dump = &file[..Version::SIZE + 120];
+
+        match Instrument::read(&mut &dump[..]) {
+            Err(err) => assert_eq!(err.kind(), ParseErrorKind::TooShort),
+            Ok(_) => panic!("Truncated instrument file should not parse"),
+        }
+    }
+
+    #[test]
+    fn truncated_hypersynth_errors() {
+        let song = test_file();
+        let ver = song.version;
+        let mut w = Writer::new(vec![0; Instrument::INSTRUMENT_MEMORY_SIZE]);
+        song.instruments[5].write(ver, &mut w);
+        let bytes = w.finish();
+
+        // the 16 chords end within the instrument memory
+        assert!(Instrument::from_reader(&mut Reader::new(bytes.clone()), 5, ver).is_ok());
+
+        let truncated = bytes[..150].to_vec();
+        let err = Instrument::from_reader(&mut Reader::new(truncated), 5, ver).unwrap_err();
+        assert_eq!(err.kind(), ParseErrorKind::TooShort);
+    }
+
+    #[test]
+    fn garbage_instruments_do_not_panic() {
+        // xorshift, to get reproducible garbage without extra dependency
+        let mut state: u32 = 0x2545_F491;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state
+        };
+
+        let full_len = Version::SIZE + Instrument::INSTRUMENT_MEMORY_SIZE + Table::V4_SIZE + 64;
+        for i in 0..5000 {
+            let len = next() as usize % full_len;
+            let mut bytes: Vec<u8> = (0..len).map(|_| next() as u8).collect();
+
+            // most inputs get a valid magic and a plausible version to
+            // reach the instrument parsers
+            if i % 8 != 0 && len > Version::SIZE {
+                bytes[..10].copy_from_slice(b"M8VERSION\0");
+                bytes[11] = (next() % 7) as u8;
+                bytes[14] = (next() % 8) as u8;
+            }
+
+            let _ = parse_instrument_nopanic(&bytes);
+        }
+    }
+
+    #[test]
+    fn read_fifth_instrument_at_offset() {
+        let song = test_file();
+        let ver = song.version;
+        let offset = song.offsets().instruments + 4 * Instrument::bank_stride(ver);
+
+        let mut f = std::fs::File::open("./examples/songs/TEST-FILE.m8s").unwrap();
+        let instr = Instrument::read_at(&mut f, offset as u64, ver).unwrap();
+        assert_eq!(instr.name(), Some("FM"));
+
+        let write = |i: &Instrument| {
+            let mut w = Writer::new(vec![0; Instrument::INSTRUMENT_MEMORY_SIZE]);
+            i.write(ver, &mut w);
+            w.finish()
+        };
+        assert_eq!(write(&instr), write(&song.instruments[4]));
+
+        let past_end = std::fs::metadata("./examples/songs/TEST-FILE.m8s")
+            .unwrap()
+            .len();
+        let err = Instrument::read_at(&mut f, past_end, ver).unwrap_err();
+        assert_eq!(err.kind(), ParseErrorKind::UnexpectedEof);
+
+        struct Unseekable;
+        impl std::io::Read for Unseekable {
+            fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+                Ok(0)
+            }
+        }
+        impl std::io::Seek for Unseekable {
+            fn seek(&mut self, _: std::io::SeekFrom) -> std::io::Result<u64> {
+                Err(std::io::ErrorKind::Unsupported.into())
+            }
+        }
+        let err = Instrument::read_at(&mut Unseekable, 0, ver).unwrap_err();
+        assert_eq!(err.kind(), ParseErrorKind::Io);
+    }
+
+    #[test]
+    fn exported_m8i_reads_back() {
+        let ver = Version {
+            major: 4,
+            minor: 1,
+            patch: 2,
+        };
+        let mut fm = FMSynth::default_for(ver);
+        fm.name = "EXPORT".to_string();
+        fm.algo = FmAlgo(3);
+        fm.synth_params.set_eq(2);
+        let instr = Instrument::FMSynth(fm);
+
+        let mut eq = Equ::default();
+        eq.mid.level = 0x10;
+        let bytes = instr.write_m8i(ver, Some(&eq));
+        assert_eq!(bytes.len(), 375);
+
+        let parsed = Instrument::read(&mut &bytes[..]).unwrap();
+        assert_eq!(parsed.instrument, instr);
+        assert_eq!(parsed.eq, Some(eq));
+        assert!(parsed.table.unwrap().is_empty());
+        assert_eq!(read_header(&mut Reader::new(bytes)).unwrap().version, ver);
+    }
+
+    #[test]
+    fn exported_m8i_header_matches_device() {
+        let bytes = std::fs::read("./examples/instruments/FMDUBSTAB_4_1.m8i")
+            .expect("Could not open FMDUBSTAB_4_1");
+        let parsed = Instrument::read(&mut &bytes[..]).unwrap();
+        let ver = read_header(&mut Reader::new(bytes.clone()))
+            .unwrap()
+            .version;
+
+        let exported = parsed.instrument.write_m8i(ver, None);
+        assert_eq!(&exported[..Version::SIZE], &bytes[..Version::SIZE]);
+    }
+
+    #[test]
+    fn try_from_bytes_matches_read() {
+        let path = "./examples/instruments/FMDUBSTAB_4_1.m8i";
+        let bytes = std::fs::read(path).expect("Could not open FMDUBSTAB_4_1");
+        let mut f = std::fs::File::open(path).unwrap();
+
+        let from_bytes = Instrument::try_from(&bytes[..]).unwrap();
+        assert_eq!(from_bytes, Instrument::read(&mut f).unwrap().instrument);
+        assert!(Instrument::try_from(&bytes[..10]).is_err());
+    }
+
+    #[test]
+    fn instrument_table_round_trip() {
+        let mut file = std::fs::read("./examples/instruments/FMDUBSTAB_4_1.m8i")
+            .expect("Could not open FMDUBSTAB_4_1");
+        let table_start = Version::SIZE + Instrument::INSTRUMENT_MEMORY_SIZE;
+        // transpose, velocity, TPO 0x80, empty fx2 & fx3
+        file[table_start..table_start + 8]
+            .copy_from_slice(&[0x0C, 0x40, 0x18, 0x80, 0xFF, 0x00, 0xFF, 0x00]);
+
+        let parsed = Instrument::read_from_reader(&mut Reader::new(file.clone())).unwrap();
+        let table = parsed
+            .table
+            .as_ref()
+            .expect("Instrument table should be parsed");
+        assert_eq!(table.steps[0].transpose, 0x0C);
+        assert_eq!(table.steps[0].velocity, 0x40);
+        assert_eq!(table.steps[0].fx1.value, 0x80);
+        assert!(table.steps[1].is_empty());
+
+        let mut blank = file.clone();
+        blank[table_start..table_start + Table::V4_SIZE].fill(0);
+        let mut w = Writer::new(blank);
+        let ver = Version {
+            major: 4,
+            minor: 2,
+            patch: 0,
+        };
+        parsed.write(ver, &mut w);
+        assert_eq!(w.finish(), file);
+    }
+
+    #[test]
+    fn bank_sorted_by_name() {
+        let ver = Version::default();
+        let named = |name: &str| {
+            let mut instr = Instrument::WavSynth(WavSynth::default_for(ver));
+            instr.set_name(name.to_string());
+            instr
+        };
+
+        let bank = vec![
+            named("bass"),
+            Instrument::None,
+            named("Lead"),
+            named("BASS"),
+            named("arp"),
+        ];
+
+        let order: Vec<usize> = sorted_by_name(&bank)
+            .iter()
+            .map(|(slot, _)| *slot)
+            .collect();
+        assert_eq!(order, vec![4, 0, 3, 2, 1]);
+    }
+
+    #[test]
+    fn bank_instruments_using_eq() {
+        let ver = Version::default();
+        let with_eq = |mut instr: Instrument, eq: u8| {
+            instr.set_eq(eq);
+            instr
+        };
+
+        let bank = vec![
+            with_eq(Instrument::WavSynth(WavSynth::default_for(ver)), 2),
+            Instrument::MIDIOut(MIDIOut::default_for(ver)),
+            with_eq(Instrument::FMSynth(FMSynth::default_for(ver)), 1),
+            Instrument::None,
+            with_eq(Instrument::Sampler(Sampler::default_for(ver)), 2),
+        ];
+
+        assert_eq!(instruments_using_eq(&bank, 2), vec![0, 4]);
+        assert!(instruments_using_eq(&bank, 3).is_empty());
+    }
+
+    #[test]
+    fn rename_bank_prefix() {
+        let ver = Version::default();
+        let named = |name: &str| {
+            let mut instr = Instrument::Sampler(Sampler::default_for(ver));
+            instr.set_name(name.to_string());
+            instr
+        };
+
+        let mut bank = vec![named("KICK"), Instrument::None, named("OPENHIHAT01")];
+        rename_bank(&mut bank, |slot, name| format!("DR_{}{}", name, slot));
+
+        assert_eq!(bank[0].name(), Some("DR_KICK0"));
+        assert!(bank[1].is_empty());
+        assert_eq!(bank[2].name(), Some("DR_OPENHIHAT"));
+        assert_eq!(sanitize_name("BASS\u{e9}\tX"), "BASS__X");
+    }
+
+    #[test]
+    fn midi_out_reserved_bytes_round_trip() {
+        let song = test_file();
+        let ver = song.version;
+        let mut w = Writer::new(vec![0; Instrument::INSTRUMENT_MEMORY_SIZE]);
+        song.instruments[6].write(ver, &mut w);
+        let mut bytes = w.finish();
+
+        // kind, name, transpose, table tick, port, channel, bank, program
+        let reserved_offset = 1 + 12 + 6;
+        bytes[reserved_offset..reserved_offset + 3].copy_from_slice(&[1, 2, 3]);
+
+        let instr = Instrument::from_reader(&mut Reader::new(bytes.clone()), 6, ver).unwrap();
+        let Instrument::MIDIOut(mo) = &instr else {
+            panic!("Instrument 6 should be a MIDIOut")
+        };
+        assert_eq!(mo.reserved, [1, 2, 3]);
+
+        let mut w = Writer::new(vec![0; Instrument::INSTRUMENT_MEMORY_SIZE]);
+        instr.write(ver, &mut w);
+        assert_eq!(w.finish(), bytes);
+    }
+
+    #[test]
+    fn every_parsed_instrument_writes_back() {
+        let song = test_file();
+        let ver = song.version;
+        for (slot, instr) in song.instruments.iter().enumerate().take(8) {
+            let mut w = Writer::new(vec![0; Instrument::INSTRUMENT_MEMORY_SIZE]);
+            instr.write(ver, &mut w);
+
+            let mut reader = Reader::new(w.finish());
+            let reread = Instrument::from_reader(&mut reader, slot as u8, ver).unwrap();
+            assert_eq!(&reread, instr, "slot {}", slot);
+        }
+    }
+
+    #[test]
+    fn written_instrument_is_retrievable() {
+        let song = test_file();
+        let mut w = Writer::new(vec![0; Instrument::INSTRUMENT_MEMORY_SIZE]);
+        song.instruments[4].write(song.version, &mut w);
+
+        assert_eq!(w.as_slice()[0], 0x04);
+        let bytes = w.finish();
+        assert_eq!(bytes.len(), Instrument::INSTRUMENT_MEMORY_SIZE);
+        assert_eq!(&bytes[1..3], b"FM");
+    }
+
+    #[test]
+    fn two_slot_bank_uses_stride() {
+        let song = test_file();
+        let ver = song.version;
+        let stride = Instrument::bank_stride(ver);
+
+        let mut w = Writer::new(vec![0; 2 * stride]);
+        song.instruments[1].write(ver, &mut w);
+        w.seek(stride);
+        song.instruments[4].write(ver, &mut w);
+
+        let mut reader = Reader::new(w.finish());
+        let first = Instrument::from_reader(&mut reader, 0, ver).unwrap();
+        assert_eq!(reader.pos(), stride);
+        let second = Instrument::from_reader(&mut reader, 1, ver).unwrap();
+
+        assert_eq!(first.name(), song.instruments[1].name());
+        assert_eq!(second.name(), Some("FM"));
+    }
+
+    #[test]
+    fn mod_padding_round_trips() {
+        let song = test_file();
+        let ver = song.version;
+        let mut instr = song.instruments[4].clone();
+        let params = instr.synth_params_mut().unwrap();
+        let len = FMSynth::MOD_OFFSET;
+        assert!(len > 0);
+        // the last byte holds the EQ from 4.1, kept as 0 in the padding
+        params.mod_padding = (1..len as u8).chain([0]).collect();
+        let padding = params.mod_padding.clone();
+
+        let mut w = Writer::new(vec![0; Instrument::INSTRUMENT_MEMORY_SIZE]);
+        instr.write(ver, &mut w);
+        let bytes = w.finish();
+
+        let reread = Instrument::from_reader(&mut Reader::new(bytes.clone()), 4, ver).unwrap();
+        assert_eq!(reread.synth_params().unwrap().mod_padding, padding);
+        assert_eq!(reread, instr);
+
+        let mut w = Writer::new(vec![0; Instrument::INSTRUMENT_MEMORY_SIZE]);
+        reread.write(ver, &mut w);
+        assert_eq!(w.finish(), bytes);
+    }
+
+    #[test]
+    fn fm_extra_command_depends_on_version() {
+        let v5 = Version {
+            major: 5,
+            minor: 0,
+            patch: 0,
+        };
+        let v6 = Version {
+            major: 6,
+            minor: 0,
+            patch: 1,
+        };
+        let instr = Instrument::FMSynth(FMSynth::default_for(v6));
+        let render = |fx: FX, ver: Version| {
+            fx.print(FX::fx_command_names(ver), instr.instr_command_text(ver))
+        };
+
+        // first command after the instrument base and modulator commands
+        let extra = (CommandPack::INSTRUMENT_COMMAND_OFFSET
+            + CommandPack::BASE_INSTRUMENT_COMMAND_COUNT
+            + Mod::COMMAND_PER_MOD * SynthParams::MODULATOR_COUNT) as u8;
+
+        let fx = FX {
+            command: extra,
+            value: 0x12,
+        };
+        assert_eq!(render(fx, v5), "FMP12");
+        assert_eq!(render(fx, v6), "SNC12");
+
+        let fx = FX {
+            command: extra + 1,
+            value: 0x34,
+        };
+        assert_eq!(render(fx, v6), "ERR34");
+    }
+
+    #[test]
+    fn out_of_range_names_do_not_panic() {
+        let ver = Version::default();
+        assert_eq!(lookup(&["A", "B"], 2), "???");
+        assert_eq!(FmAlgo(0x20).str(), "???");
+        assert!(FmAlgo(0x20).routing().is_empty());
+        assert_eq!(LimitType(0x20).str(), "???");
+        assert_eq!(format!("{:?}", LimitType(0x20)), "???");
+
+        let mut ws = WavSynth::default_for(ver);
+        ws.synth_params.filter_type = 0xFE;
+        assert_eq!(ws.human_readable_filter(), "???");
+
+        let mut ms = MacroSynth::default_for(ver);
+        ms.synth_params.filter_type = 0xFE;
+        assert_eq!(ms.human_readable_filter(), "???");
+
+        let mut fm = FMSynth::default_for(ver);
+        fm.synth_params.filter_type = 0xFE;
+        assert_eq!(fm.human_readable_filter(), "???");
+
+        let mut hs = HyperSynth::default_for(ver);
+        hs.synth_params.filter_type = 0xFE;
+        assert_eq!(hs.human_readable_filter(), "???");
+
+        let mut mo = MIDIOut::default_for(ver);
+        mo.port = 0x10;
+        assert_eq!(mo.human_readable_port(), "???");
+
+        let mut ex = ExternalInst::default_for(ver);
+        ex.port = 0x10;
+        assert_eq!(ex.human_readable_port(), "???");
+
+        ex.synth_params.mods[0] = AHDEnv {
+            dest: 0x40,
+            ..Default::default()
+        }
+        .to_mod();
+        let rows = Instrument::External(ex).modulation_table(ver);
+        assert_eq!(rows[0].destination, "???");
+    }
+}
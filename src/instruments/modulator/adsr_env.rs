@@ -3,7 +3,7 @@ use crate::Version;
 use super::*;
 use crate::writer::Writer;
 
-#[derive(PartialEq, Debug, Clone)]
+#[derive(PartialEq, Debug, Clone, Default)]
 pub struct ADSREnv {
     pub dest: u8,
     pub amount: u8,
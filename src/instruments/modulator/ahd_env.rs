@@ -9,6 +9,10 @@ pub struct AHDEnv {
     pub attack: u8,
     pub hold: u8,
     pub decay: u8,
+
+    /// Last byte of the modulator, unused by the envelope and kept to
+    /// write it back as read.
+    pub reserved: u8,
 }
 
 const AHDENV_COMMAND_NAMES: [[&'static str; 5]; 4] = [
@@ -30,8 +34,8 @@ impl AHDEnv {
             attack: reader.read(),
             hold: reader.read(),
             decay: reader.read(),
+            reserved: reader.read(),
         };
-        reader.read();
         Ok(r)
     }
 
@@ -42,6 +46,7 @@ impl AHDEnv {
             attack: reader.read(),
             hold: reader.read(),
             decay: reader.read(),
+            reserved: reader.read(),
         })
     }
 
@@ -50,6 +55,7 @@ impl AHDEnv {
         w.write(self.attack);
         w.write(self.hold);
         w.write(self.decay);
+        w.write(self.reserved);
     }
 
     pub fn to_mod(self) -> Mod {
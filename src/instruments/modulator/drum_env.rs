@@ -9,7 +9,7 @@ const DRUMENV_COMMAND_NAMES: [[&'static str; 5]; 4] = [
     ["EA4", "PK4", "BO4", "DE4", "ET4"],
 ];
 
-#[derive(PartialEq, Debug, Clone)]
+#[derive(PartialEq, Debug, Clone, Default)]
 pub struct DrumEnv {
     pub dest: u8,
     pub amount: u8,
@@ -2,7 +2,7 @@ use num_enum::{IntoPrimitive, TryFromPrimitive};
 
 use crate::{writer::Writer, Version};
 
-use super::{M8Result, Mod, ParseError, Reader};
+use super::{M8Result, Mod, ParseError, ParseErrorKind, Reader};
 
 #[repr(u8)]
 #[allow(non_camel_case_types)]
@@ -42,7 +42,7 @@ pub enum LfoTriggerMode {
     ONCE,
 }
 
-#[derive(PartialEq, Debug, Clone)]
+#[derive(PartialEq, Debug, Clone, Default)]
 pub struct LFO {
     pub shape: LfoShape,
     pub dest: u8,
@@ -69,13 +69,19 @@ impl LFO {
         let dest = reader.read();
         let trigger = reader.read();
         let r = Self {
-            shape: shape
-                .try_into()
-                .map_err(|_| ParseError(format!("Invalid LFO shape {}", shape)))?,
+            shape: shape.try_into().map_err(|_| {
+                ParseError::new(
+                    ParseErrorKind::InvalidValue(shape),
+                    format!("Invalid LFO shape {}", shape),
+                )
+            })?,
             dest,
-            trigger_mode: trigger
-                .try_into()
-                .map_err(|_| ParseError(format!("Invalid lfo trigger mode {}", trigger)))?,
+            trigger_mode: trigger.try_into().map_err(|_| {
+                ParseError::new(
+                    ParseErrorKind::InvalidValue(trigger),
+                    format!("Invalid lfo trigger mode {}", trigger),
+                )
+            })?,
             freq: reader.read(),
             amount: reader.read(),
             retrigger: reader.read(),
@@ -102,12 +108,18 @@ impl LFO {
         Ok(Self {
             dest,
             amount,
-            shape: shape
-                .try_into()
-                .map_err(|_| ParseError(format!("Invalid LFO shape {}", shape)))?,
-            trigger_mode: trigger_mode
-                .try_into()
-                .map_err(|_| ParseError(format!("Invalid lfo trigger mode {}", trigger_mode)))?,
+            shape: shape.try_into().map_err(|_| {
+                ParseError::new(
+                    ParseErrorKind::InvalidValue(shape),
+                    format!("Invalid LFO shape {}", shape),
+                )
+            })?,
+            trigger_mode: trigger_mode.try_into().map_err(|_| {
+                ParseError::new(
+                    ParseErrorKind::InvalidValue(trigger_mode),
+                    format!("Invalid lfo trigger mode {}", trigger_mode),
+                )
+            })?,
             freq,
             retrigger,
         })
@@ -1,5 +1,6 @@
 use crate::{reader::*, writer::Writer};
 
+use super::params;
 use super::Version;
 
 mod adsr_env;
@@ -16,6 +17,62 @@ pub use lfo::*;
 pub use tracking_env::*;
 pub use trig_env::*;
 
+/// One line of the modulation matrix of an instrument, as displayed
+/// on the instrument MOD page.
+#[derive(PartialEq, Debug, Clone)]
+pub struct ModRow {
+    /// Modulator slot, from 0 to 3
+    pub slot: usize,
+
+    /// Human readable kind of modulator ("LFO", "AHD ENV", ...)
+    pub mod_type: &'static str,
+
+    /// Name of the modulated parameter, depend on the instrument
+    pub destination: &'static str,
+
+    /// Modulation amount, centered on 0x80
+    pub amount: i8,
+
+    /// Remaining parameters of the modulator, with their names.
+    pub params: Vec<(&'static str, u8)>,
+}
+
+/// Kind of a modulator, without its parameters
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum ModKind {
+    AHDEnv,
+    ADSREnv,
+    DrumEnv,
+    LFO,
+    TrigEnv,
+    TrackingEnv,
+}
+
+impl ModKind {
+    /// Every kind, in the order of their type number
+    pub const ALL: [ModKind; 6] = [
+        ModKind::AHDEnv,
+        ModKind::ADSREnv,
+        ModKind::DrumEnv,
+        ModKind::LFO,
+        ModKind::TrigEnv,
+        ModKind::TrackingEnv,
+    ];
+
+    /// Modulator of this kind without destination, all its parameters
+    /// at 0.
+    pub fn zeroed(self) -> Mod {
+        match self {
+            ModKind::AHDEnv => Mod::AHDEnv(AHDEnv::default()),
+            ModKind::ADSREnv => Mod::ADSREnv(ADSREnv::default()),
+            ModKind::DrumEnv => Mod::DrumEnv(DrumEnv::default()),
+            ModKind::LFO => Mod::LFO(LFO::default()),
+            ModKind::TrigEnv => Mod::TrigEnv(TrigEnv::default()),
+            ModKind::TrackingEnv => Mod::TrackingEnv(TrackingEnv::default()),
+        }
+    }
+}
+
 #[derive(PartialEq, Debug, Clone)]
 pub enum Mod {
     AHDEnv(AHDEnv),
@@ -33,6 +90,93 @@ impl Mod {
     /// Number of commands associated to each modulator
     pub const COMMAND_PER_MOD: usize = 5;
 
+    /// Destination index of the modulator, to be looked up in the
+    /// instrument destination names.
+    pub fn dest(&self) -> u8 {
+        match self {
+            Mod::AHDEnv(env) => env.dest,
+            Mod::ADSREnv(env) => env.dest,
+            Mod::DrumEnv(env) => env.dest,
+            Mod::LFO(lfo) => lfo.dest,
+            Mod::TrigEnv(env) => env.dest,
+            Mod::TrackingEnv(env) => env.dest,
+        }
+    }
+
+    pub fn amount(&self) -> u8 {
+        match self {
+            Mod::AHDEnv(env) => env.amount,
+            Mod::ADSREnv(env) => env.amount,
+            Mod::DrumEnv(env) => env.amount,
+            Mod::LFO(lfo) => lfo.amount,
+            Mod::TrigEnv(env) => env.amount,
+            Mod::TrackingEnv(env) => env.amount,
+        }
+    }
+
+    /// Kind of the modulator, without its parameters
+    pub fn kind(&self) -> ModKind {
+        match self {
+            Mod::AHDEnv(_) => ModKind::AHDEnv,
+            Mod::ADSREnv(_) => ModKind::ADSREnv,
+            Mod::DrumEnv(_) => ModKind::DrumEnv,
+            Mod::LFO(_) => ModKind::LFO,
+            Mod::TrigEnv(_) => ModKind::TrigEnv,
+            Mod::TrackingEnv(_) => ModKind::TrackingEnv,
+        }
+    }
+
+    /// Name of the modulator kind as displayed by the M8
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Mod::AHDEnv(_) => "AHD ENV",
+            Mod::ADSREnv(_) => "ADSR ENV",
+            Mod::DrumEnv(_) => "DRUM ENV",
+            Mod::LFO(_) => "LFO",
+            Mod::TrigEnv(_) => "TRIG ENV",
+            Mod::TrackingEnv(_) => "TRACKING",
+        }
+    }
+
+    /// Modulator specific parameters (everything but destination and amount)
+    pub fn params(&self) -> Vec<(&'static str, u8)> {
+        match self {
+            Mod::AHDEnv(env) => vec![
+                (params::ATTACK, env.attack),
+                (params::HOLD, env.hold),
+                (params::DECAY, env.decay),
+            ],
+            Mod::ADSREnv(env) => vec![
+                (params::ATTACK, env.attack),
+                (params::DECAY, env.decay),
+                (params::SUSTAIN, env.sustain),
+                (params::RELEASE, env.release),
+            ],
+            Mod::DrumEnv(env) => vec![
+                (params::PEAK, env.peak),
+                (params::BODY, env.body),
+                (params::DECAY, env.decay),
+            ],
+            Mod::LFO(lfo) => vec![
+                (params::LFOSHAPE, lfo.shape.into()),
+                (params::TRIGGER, lfo.trigger_mode.into()),
+                (params::FREQ, lfo.freq),
+                ("RETRIG", lfo.retrigger),
+            ],
+            Mod::TrigEnv(env) => vec![
+                (params::ATTACK, env.attack),
+                (params::HOLD, env.hold),
+                (params::DECAY, env.decay),
+                (params::SOURCE, env.src),
+            ],
+            Mod::TrackingEnv(env) => vec![
+                (params::SOURCE, env.src),
+                ("LOW", env.lval),
+                ("HIGH", env.hval),
+            ],
+        }
+    }
+
     pub fn command_name(&self, ver: Version, mod_id: usize) -> &'static [&'static str] {
         match self {
             Mod::AHDEnv(_) => AHDEnv::command_names(ver, mod_id),
@@ -58,10 +202,15 @@ impl Mod {
             3 => Mod::LFO(LFO::from_reader3(reader, dest)?),
             4 => Mod::TrigEnv(TrigEnv::from_reader(reader, dest)?),
             5 => Mod::TrackingEnv(TrackingEnv::from_reader(reader, dest)?),
-            x => return Err(ParseError(format!("Unknown mod type {}", x))),
+            x => {
+                return Err(ParseError::new(
+                    ParseErrorKind::InvalidModType(x),
+                    format!("Unknown mod type {}", x),
+                ))
+            }
         };
 
-        reader.set_pos(start_pos + Self::SIZE);
+        reader.resync(start_pos + Self::SIZE, "Mod");
         Ok(r)
     }
 
@@ -98,3 +247,95 @@ impl Mod {
         w.seek(start + Self::SIZE);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::instruments::modulator::*;
+
+    #[test]
+    fn typed_mods_round_trip() {
+        let mods = [
+            AHDEnv {
+                dest: 1,
+                amount: 0xF0,
+                attack: 2,
+                hold: 3,
+                decay: 4,
+                reserved: 0x80,
+            }
+            .to_mod(),
+            LFO {
+                shape: LfoShape::SIN,
+                dest: 2,
+                trigger_mode: LfoTriggerMode::RETRIG,
+                freq: 0x20,
+                amount: 0x40,
+                retrigger: 0,
+            }
+            .to_mod(),
+        ];
+
+        let mut w = Writer::with_capacity(Mod::SIZE * mods.len());
+        for m in &mods {
+            m.write(&mut w);
+        }
+
+        let mut reader = Reader::new(w.finish());
+        for m in &mods {
+            assert_eq!(&Mod::from_reader(&mut reader).unwrap(), m);
+        }
+    }
+
+    #[test]
+    fn ahd_env_keeps_trailing_byte() {
+        let bytes = vec![0x00, 0, 0, 0, 0, 0x42];
+        let mut reader = Reader::new(bytes.clone());
+        let m = Mod::from_reader(&mut reader).unwrap();
+
+        let mut w = Writer::new(vec![0; Mod::SIZE]);
+        m.write(&mut w);
+        assert_eq!(w.finish(), bytes);
+    }
+
+    #[test]
+    fn unknown_mod_type_is_an_error() {
+        let mut reader = Reader::new(vec![0x61, 0, 0, 0, 0, 0]);
+        let err = Mod::from_reader(&mut reader).unwrap_err();
+        assert_eq!(err.kind(), ParseErrorKind::InvalidModType(6));
+    }
+
+    #[test]
+    #[cfg(all(feature = "offset-check", debug_assertions))]
+    #[should_panic(expected = "Mod over-read")]
+    fn over_read_mod_is_asserted() {
+        let mut reader = Reader::new(vec![0; Mod::SIZE + 1]);
+        reader.read_bytes(Mod::SIZE + 1);
+        reader.resync(Mod::SIZE, "Mod");
+    }
+
+    #[test]
+    fn envelope_source_names() {
+        let trig = |src| TrigEnv {
+            dest: 1,
+            amount: 0xFF,
+            attack: 0,
+            hold: 0,
+            decay: 0x80,
+            src,
+        };
+        assert_eq!(trig(0).source_name(), "NOTE");
+        assert_eq!(trig(2).source_name(), "MOD2");
+        assert_eq!(trig(0x40).source_name(), "???");
+
+        let tracking = |src| TrackingEnv {
+            dest: 1,
+            amount: 0xFF,
+            src,
+            lval: 0,
+            hval: 0xFF,
+        };
+        assert_eq!(tracking(0).source_name(), "NOTE");
+        assert_eq!(tracking(1).source_name(), "VEL");
+        assert_eq!(tracking(0xFF).source_name(), "???");
+    }
+}
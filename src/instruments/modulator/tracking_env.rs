@@ -1,3 +1,4 @@
+use crate::instruments::lookup;
 use crate::Version;
 
 use super::{M8Result, Reader, Writer};
@@ -9,7 +10,17 @@ const TRACKING_ENV_COMMAND_NAMES: [[&'static str; 5]; 4] = [
     ["TA4", "TS4", "TL4", "TH4", "TX4"],
 ];
 
-#[derive(PartialEq, Debug, Clone)]
+#[rustfmt::skip] // Keep constants with important order vertical for maintenance
+const TRACKING_ENV_SOURCES : [&str; 6] = [
+    "NOTE",
+    "VEL",
+    "MOD1",
+    "MOD2",
+    "MOD3",
+    "MOD4",
+];
+
+#[derive(PartialEq, Debug, Clone, Default)]
 pub struct TrackingEnv {
     pub dest: u8,
     pub amount: u8,
@@ -19,6 +30,11 @@ pub struct TrackingEnv {
 }
 
 impl TrackingEnv {
+    /// Name of the tracked source, "???" for an unknown one
+    pub fn source_name(&self) -> &'static str {
+        lookup(&TRACKING_ENV_SOURCES, self.src)
+    }
+
     pub fn command_name(_ver: Version, env_id: usize) -> &'static [&'static str] {
         &TRACKING_ENV_COMMAND_NAMES[env_id]
     }
@@ -1,8 +1,9 @@
+use crate::instruments::lookup;
 use crate::Version;
 
 use super::{M8Result, Reader, Writer};
 
-#[derive(PartialEq, Debug, Clone)]
+#[derive(PartialEq, Debug, Clone, Default)]
 pub struct TrigEnv {
     pub dest: u8,
     pub amount: u8,
@@ -19,7 +20,21 @@ const TRIGENV_COMMAND_NAMES: [[&'static str; 5]; 4] = [
     ["EA4", "AT4", "HO4", "SU4", "ET4"],
 ];
 
+#[rustfmt::skip] // Keep constants with important order vertical for maintenance
+const TRIGENV_SOURCES : [&str; 5] = [
+    "NOTE",
+    "MOD1",
+    "MOD2",
+    "MOD3",
+    "MOD4",
+];
+
 impl TrigEnv {
+    /// Name of the trigger source, "???" for an unknown one
+    pub fn source_name(&self) -> &'static str {
+        lookup(&TRIGENV_SOURCES, self.src)
+    }
+
     pub fn command_name(_ver: Version, mod_id: usize) -> &'static [&'static str] {
         &TRIGENV_COMMAND_NAMES[mod_id]
     }
@@ -0,0 +1,161 @@
+use super::{Instrument, SynthParams};
+
+/// Linear interpolation of a byte parameter, rounded to the nearest value
+fn lerp_u8(a: u8, b: u8, t: f32) -> u8 {
+    (a as f32 + (b as f32 - a as f32) * t)
+        .round()
+        .clamp(0.0, 255.0) as u8
+}
+
+/// Same as [`lerp_u8`] for a byte storing a signed offset
+fn lerp_i8(a: u8, b: u8, t: f32) -> u8 {
+    let (a, b) = (a as i8 as f32, b as i8 as f32);
+    (a + (b - a) * t).round().clamp(-128.0, 127.0) as i8 as u8
+}
+
+fn lerp_params(out: &mut SynthParams, a: &SynthParams, b: &SynthParams, t: f32) {
+    out.volume = lerp_u8(a.volume, b.volume, t);
+    out.pitch = lerp_i8(a.pitch, b.pitch, t);
+    out.fine_tune = lerp_u8(a.fine_tune, b.fine_tune, t);
+    out.filter_cutoff = lerp_u8(a.filter_cutoff, b.filter_cutoff, t);
+    out.filter_res = lerp_u8(a.filter_res, b.filter_res, t);
+    out.amp = lerp_u8(a.amp, b.amp, t);
+    out.mixer_pan = lerp_u8(a.mixer_pan, b.mixer_pan, t);
+    out.mixer_dry = lerp_u8(a.mixer_dry, b.mixer_dry, t);
+    out.mixer_chorus = lerp_u8(a.mixer_chorus, b.mixer_chorus, t);
+    out.mixer_delay = lerp_u8(a.mixer_delay, b.mixer_delay, t);
+    out.mixer_reverb = lerp_u8(a.mixer_reverb, b.mixer_reverb, t);
+}
+
+impl Instrument {
+    /// Tell if [`Instrument::lerp`] can blend the two instruments: they
+    /// must be of the same (non empty) kind.
+    pub fn can_morph_with(&self, other: &Instrument) -> bool {
+        !self.is_empty() && std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+
+    /// Blend two instruments, `t` going from 0.0 (`self`) to 1.0
+    /// (`other`). Continuous parameters are interpolated, everything
+    /// else (names, enumerations, sample path, modulators...) is taken
+    /// from `self` below 0.5 and from `other` from 0.5. Instruments
+    /// that can't morph snap the same way.
+    pub fn lerp(&self, other: &Instrument, t: f32) -> Instrument {
+        let t = t.clamp(0.0, 1.0);
+        let mut out = if t < 0.5 { self.clone() } else { other.clone() };
+        if !self.can_morph_with(other) {
+            return out;
+        }
+
+        if let (Some(a), Some(b), Some(params)) = (
+            self.synth_params(),
+            other.synth_params(),
+            out.synth_params_mut(),
+        ) {
+            lerp_params(params, a, b, t);
+        }
+
+        match (&mut out, self, other) {
+            (Instrument::WavSynth(o), Instrument::WavSynth(a), Instrument::WavSynth(b)) => {
+                o.size = lerp_u8(a.size, b.size, t);
+                o.mult = lerp_u8(a.mult, b.mult, t);
+                o.warp = lerp_u8(a.warp, b.warp, t);
+                o.scan = lerp_u8(a.scan, b.scan, t);
+            }
+            (Instrument::MacroSynth(o), Instrument::MacroSynth(a), Instrument::MacroSynth(b)) => {
+                o.timbre = lerp_u8(a.timbre, b.timbre, t);
+                o.color = lerp_u8(a.color, b.color, t);
+                o.degrade = lerp_u8(a.degrade, b.degrade, t);
+                o.redux = lerp_u8(a.redux, b.redux, t);
+            }
+            (Instrument::Sampler(o), Instrument::Sampler(a), Instrument::Sampler(b)) => {
+                o.start = lerp_u8(a.start, b.start, t);
+                o.loop_start = lerp_u8(a.loop_start, b.loop_start, t);
+                o.length = lerp_u8(a.length, b.length, t);
+                o.degrade = lerp_u8(a.degrade, b.degrade, t);
+            }
+            (Instrument::FMSynth(o), Instrument::FMSynth(a), Instrument::FMSynth(b)) => {
+                for (i, op) in o.operators.iter_mut().enumerate() {
+                    let (oa, ob) = (&a.operators[i], &b.operators[i]);
+                    op.ratio = lerp_u8(oa.ratio, ob.ratio, t);
+                    op.ratio_fine = lerp_u8(oa.ratio_fine, ob.ratio_fine, t);
+                    op.level = lerp_u8(oa.level, ob.level, t);
+                    op.feedback = lerp_u8(oa.feedback, ob.feedback, t);
+                    op.mod_a = lerp_u8(oa.mod_a, ob.mod_a, t);
+                    op.mod_b = lerp_u8(oa.mod_b, ob.mod_b, t);
+                }
+                o.mod1 = lerp_u8(a.mod1, b.mod1, t);
+                o.mod2 = lerp_u8(a.mod2, b.mod2, t);
+                o.mod3 = lerp_u8(a.mod3, b.mod3, t);
+                o.mod4 = lerp_u8(a.mod4, b.mod4, t);
+            }
+            (Instrument::HyperSynth(o), Instrument::HyperSynth(a), Instrument::HyperSynth(b)) => {
+                o.shift = lerp_u8(a.shift, b.shift, t);
+                o.swarm = lerp_u8(a.swarm, b.swarm, t);
+                o.width = lerp_u8(a.width, b.width, t);
+                o.subosc = lerp_u8(a.subosc, b.subosc, t);
+            }
+            (Instrument::External(o), Instrument::External(a), Instrument::External(b)) => {
+                o.cca.value = lerp_u8(a.cca.value, b.cca.value, t);
+                o.ccb.value = lerp_u8(a.ccb.value, b.ccb.value, t);
+                o.ccc.value = lerp_u8(a.ccc.value, b.ccc.value, t);
+                o.ccd.value = lerp_u8(a.ccd.value, b.ccd.value, t);
+            }
+            (Instrument::MIDIOut(o), Instrument::MIDIOut(a), Instrument::MIDIOut(b)) => {
+                for (i, cc) in o.custom_cc.iter_mut().enumerate() {
+                    cc.value = lerp_u8(a.custom_cc[i].value, b.custom_cc[i].value, t);
+                }
+            }
+            _ => {}
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::instruments::*;
+
+    #[test]
+    fn half_way_wavsynth_averages_cutoff() {
+        let ver = Version::default();
+        let mut a = WavSynth::default_for(ver);
+        a.name = "LOW".to_string();
+        a.synth_params.filter_cutoff = 0x20;
+        a.shape = WavShape::PULSE12;
+
+        let mut b = a.clone();
+        b.name = "HIGH".to_string();
+        b.synth_params.filter_cutoff = 0x60;
+        b.shape = WavShape::SAW;
+
+        let (a, b) = (Instrument::WavSynth(a), Instrument::WavSynth(b));
+        assert!(a.can_morph_with(&b));
+        assert!(!a.can_morph_with(&Instrument::Sampler(Sampler::default_for(ver))));
+
+        match a.lerp(&b, 0.5) {
+            Instrument::WavSynth(mid) => {
+                assert_eq!(mid.synth_params.filter_cutoff, 0x40);
+                assert_eq!(mid.shape, WavShape::SAW);
+                assert_eq!(mid.name, "HIGH");
+            }
+            other => panic!("Expected a WavSynth, got {:?}", other),
+        }
+        assert_eq!(a.lerp(&b, 0.0), a);
+        assert_eq!(a.lerp(&b, 1.0), b);
+    }
+
+    #[test]
+    fn pitch_morphs_through_zero() {
+        let mut a = WavSynth::default_for(Version::default());
+        a.synth_params.pitch = -1i8 as u8;
+        let mut b = a.clone();
+        b.synth_params.pitch = 1;
+
+        let (a, b) = (Instrument::WavSynth(a), Instrument::WavSynth(b));
+        let pitch = |t| a.lerp(&b, t).synth_params().map(|p| p.pitch);
+        assert_eq!(pitch(0.5), Some(0));
+        assert_eq!(pitch(0.0), Some(0xFF));
+        assert_eq!(pitch(1.0), Some(1));
+    }
+}
@@ -30,6 +30,30 @@ pub enum SamplePlayMode {
     BPM_PP,
 }
 
+impl SamplePlayMode {
+    /// The sample is played as a single cycle waveform (OSC modes)
+    pub fn is_oscillator(self) -> bool {
+        matches!(
+            self,
+            SamplePlayMode::OSC | SamplePlayMode::OSC_REV | SamplePlayMode::OSC_PP
+        )
+    }
+
+    /// The sample keeps playing its loop instead of stopping at its end
+    /// (loop, ping-pong and oscillator modes).
+    pub fn is_looping(self) -> bool {
+        matches!(
+            self,
+            SamplePlayMode::FWDLOOP
+                | SamplePlayMode::REVLOOP
+                | SamplePlayMode::FWD_PP
+                | SamplePlayMode::REV_PP
+                | SamplePlayMode::REP_PP
+                | SamplePlayMode::BPM_PP
+        ) || self.is_oscillator()
+    }
+}
+
 #[derive(PartialEq, Debug, Clone)]
 pub struct Sampler {
     pub number: u8,
@@ -38,6 +62,9 @@ pub struct Sampler {
     pub table_tick: u8,
     pub synth_params: SynthParams,
 
+    /// Path of the sample on the SD card. It fills the instrument
+    /// memory right after the modulators, leaving no room for per
+    /// slice data: slices are taken from the sample file itself.
     pub sample_path: String,
     pub play_mode: SamplePlayMode,
     pub slice: u8,
@@ -96,6 +123,51 @@ const DESTINATIONS : [&'static str; 14] = [
 impl Sampler {
     pub const MOD_OFFSET: usize = 29;
 
+    /// Offset of the sample path from the start of the instrument
+    /// (instrument kind byte included), right after the last modulator.
+    pub const SAMPLE_PATH_OFFSET: usize = 0x57;
+
+    /// Size of the sample path field, which runs to the end of the
+    /// instrument memory: there is no room left for key zones.
+    pub const SAMPLE_PATH_LENGTH: usize = 128;
+
+    /// Instrument as initialized by the M8 when creating a new one
+    pub fn default_for(_ver: Version) -> Self {
+        Self {
+            number: 0,
+            name: String::new(),
+            transpose: true,
+            table_tick: 1,
+            synth_params: SynthParams::m8_default(),
+
+            sample_path: String::new(),
+            play_mode: SamplePlayMode::FWD,
+            slice: 0,
+            start: 0,
+            loop_start: 0,
+            length: 0xFF,
+            degrade: 0,
+        }
+    }
+
+    pub fn is_oscillator_mode(&self) -> bool {
+        self.play_mode.is_oscillator()
+    }
+
+    pub fn is_looping(&self) -> bool {
+        self.play_mode.is_looping()
+    }
+
+    /// Set the sample path, truncated (on a character boundary) to the
+    /// size of the field.
+    pub fn set_sample_path(&mut self, path: &str) {
+        let mut end = path.len().min(Sampler::SAMPLE_PATH_LENGTH);
+        while !path.is_char_boundary(end) {
+            end -= 1;
+        }
+        self.sample_path = path[..end].to_string();
+    }
+
     pub fn command_name(&self, _ver: Version) -> &'static [&'static str] {
         &SAMPLER_FX_COMMANDS
     }
@@ -127,8 +199,9 @@ impl Sampler {
 
         self.synth_params.write(ver, w, Sampler::MOD_OFFSET);
 
-        w.seek(pos + 0x56);
-        w.write_string(&self.sample_path, 128);
+        // pos is after the instrument kind byte
+        w.seek(pos + Sampler::SAMPLE_PATH_OFFSET - 1);
+        w.write_string(&self.sample_path, Sampler::SAMPLE_PATH_LENGTH);
     }
 
     pub fn from_reader(
@@ -167,8 +240,8 @@ impl Sampler {
             SynthParams::from_reader2(reader, volume, pitch, fine_tune)?
         };
 
-        reader.set_pos(start_pos + 0x57);
-        let sample_path = reader.read_string(128);
+        reader.resync(start_pos + Sampler::SAMPLE_PATH_OFFSET, "Sampler");
+        let sample_path = reader.read_string(Sampler::SAMPLE_PATH_LENGTH);
 
         Ok(Sampler {
             number,
@@ -178,9 +251,9 @@ impl Sampler {
             synth_params,
 
             sample_path,
-            play_mode: play_mode
-                .try_into()
-                .map_err(|_| ParseError(format!("Invalid play mode")))?,
+            play_mode: play_mode.try_into().map_err(|_| {
+                ParseError::new(ParseErrorKind::InvalidValue(play_mode), "Invalid play mode")
+            })?,
             slice,
             start,
             loop_start,
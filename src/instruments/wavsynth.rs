@@ -6,6 +6,7 @@ use num_enum::IntoPrimitive;
 use num_enum::TryFromPrimitive;
 
 use super::dests;
+use super::lookup;
 use super::CommandPack;
 
 /// Wavsynth wave shape
@@ -166,6 +167,23 @@ const WAVSYNTH_FILTER_TYPES : [&'static str; 12] = [
 impl WavSynth {
     pub const MOD_OFFSET: usize = 30;
 
+    /// Instrument as initialized by the M8 when creating a new one
+    pub fn default_for(_ver: Version) -> Self {
+        Self {
+            number: 0,
+            name: String::new(),
+            transpose: true,
+            table_tick: 1,
+            synth_params: SynthParams::m8_default(),
+
+            shape: WavShape::PULSE12,
+            size: 0x20,
+            mult: 0,
+            warp: 0,
+            scan: 0,
+        }
+    }
+
     pub fn command_name(&self, _ver: Version) -> &'static [&'static str] {
         &WAVSYNTH_COMMAND_NAMES
     }
@@ -180,7 +198,7 @@ impl WavSynth {
     }
 
     pub fn human_readable_filter(&self) -> &'static str {
-        WAVSYNTH_FILTER_TYPES[self.synth_params.filter_type as usize]
+        lookup(&WAVSYNTH_FILTER_TYPES, self.synth_params.filter_type)
     }
 
     pub fn write(&self, ver: Version, w: &mut Writer) {
@@ -238,9 +256,12 @@ impl WavSynth {
             table_tick,
             synth_params,
 
-            shape: shape
-                .try_into()
-                .map_err(|_| ParseError(format!("Invalid wavsynth shape")))?,
+            shape: shape.try_into().map_err(|_| {
+                ParseError::new(
+                    ParseErrorKind::InvalidValue(shape),
+                    "Invalid wavsynth shape",
+                )
+            })?,
             size,
             mult,
             warp,
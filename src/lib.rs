@@ -6,6 +6,8 @@
 //! - [`Scale::read`]
 //! - [`Theme::read`]
 //!
+//! or use [`open`] to load any of them, the kind of file being detected.
+//!
 //! E.g.:
 //! ```
 //! use m8_files::*;
@@ -66,6 +68,7 @@
 //! to_song.song.steps[2] = final_chain;
 //! ```
 mod eq;
+mod file;
 mod fx;
 mod instruments;
 pub mod reader;
@@ -78,6 +81,7 @@ mod version;
 pub mod writer;
 
 pub use eq::*;
+pub use file::*;
 pub use fx::*;
 pub use instruments::*;
 pub use scale::*;
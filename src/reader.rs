@@ -1,11 +1,73 @@
 use std::fmt;
+use std::sync::Arc;
+
+/// Category of a parsing error, to react to a failure without
+/// looking at its message.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum ParseErrorKind {
+    /// Input is shorter than the structure to parse
+    TooShort,
+
+    /// File version not handled by the library
+    UnsupportedVersion,
+
+    /// Unknown instrument kind byte
+    UnsupportedInstrument(u8),
+
+    /// Unknown FM operator wave shape
+    InvalidFmWave(u8),
+
+    /// Unknown FM algorithm
+    InvalidAlgo(u8),
+
+    /// Unknown modulator kind
+    InvalidModType(u8),
+
+    /// Out of range value for an enumerated parameter
+    InvalidValue(u8),
+
+    /// File does not start with the M8 magic string
+    InvalidMagic,
+
+    /// Command mnemonic unknown to the version or instrument
+    UnknownCommand,
+
+    /// Data ended in the middle of a structure
+    UnexpectedEof,
+
+    /// File is not of a kind handled by the library
+    UnknownFileType,
+
+    /// Underlying file could not be read
+    Io,
+}
 
 #[derive(PartialEq, Debug)]
-pub struct ParseError(pub String);
+pub struct ParseError {
+    kind: ParseErrorKind,
+    message: String,
+}
+
+impl ParseError {
+    pub fn new(kind: ParseErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+        }
+    }
+
+    pub fn kind(&self) -> ParseErrorKind {
+        self.kind
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
 
 impl fmt::Display for ParseError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "ParseError: {}", &self.0)
+        write!(f, "ParseError: {}", &self.message)
     }
 }
 
@@ -15,35 +77,68 @@ impl std::error::Error for ParseError {}
 pub type M8Result<T> = std::result::Result<T, ParseError>;
 
 pub struct Reader {
-    buffer: Vec<u8>,
+    /// Shared between a reader and its sub readers
+    buffer: Arc<[u8]>,
+
+    /// Window of the buffer visible by this reader
+    start: usize,
+    end: usize,
+
+    /// Position relative to the start of the window
     position: usize,
 }
 
 #[allow(dead_code)]
 impl Reader {
     pub fn new(buffer: Vec<u8>) -> Self {
+        let end = buffer.len();
         Self {
-            buffer,
+            buffer: Arc::from(buffer),
+            start: 0,
+            end,
             position: 0,
         }
     }
 
+    /// Reader over `len` bytes starting at `start` (relative to this
+    /// reader window), sharing the same buffer. Reading past the window
+    /// panics like reading past the end of a whole buffer, so a record
+    /// parsed from a sub reader can't overrun into its neighbours.
+    pub fn sub(&self, start: usize, len: usize) -> Reader {
+        let sub_start = (self.start + start).min(self.end);
+        Self {
+            buffer: Arc::clone(&self.buffer),
+            start: sub_start,
+            end: (sub_start + len).min(self.end),
+            position: 0,
+        }
+    }
+
+    fn window(&self) -> &[u8] {
+        &self.buffer[self.start..self.end]
+    }
+
     pub fn len(&self) -> usize {
-        self.buffer.len()
+        self.end - self.start
     }
 
     pub fn read(&mut self) -> u8 {
         let p: usize = self.position;
-        let b = self.buffer[p];
+        let b = self.window()[p];
         self.position += 1;
         b
     }
 
     pub fn read_bytes(&mut self, n: usize) -> &[u8] {
         let p: usize = self.position;
-        let bs = &self.buffer[p..p + n];
         self.position += n;
-        bs
+        &self.window()[p..p + n]
+    }
+
+    /// Read a bipolar value, centered on 0x80, as a signed offset
+    /// (0x80 is 0, 0x00 is -128 and 0xFF is 127).
+    pub fn read_signed(&mut self) -> i8 {
+        (self.read() as i16 - 0x80) as i8
     }
 
     pub fn read_bool(&mut self) -> bool {
@@ -51,17 +146,25 @@ impl Reader {
     }
 
     pub fn read_string(&mut self, n: usize) -> String {
+        self.read_string_len(n).0
+    }
+
+    /// Read a fixed size string field of `n` bytes, returning the string
+    /// along with its length in the field (position of the terminator, or
+    /// `n` if the string fills the whole field).
+    pub fn read_string_len(&mut self, n: usize) -> (String, usize) {
         let b = self.read_bytes(n);
-        let mut end = b.iter().position(|&x| x == 0 || x == 255).unwrap_or(n);
+        let len = b.iter().position(|&x| x == 0 || x == 255).unwrap_or(n);
+        let mut end = len;
 
         while end > 0 {
             match std::str::from_utf8(&b[0..end]) {
-                Ok(str) => return str.to_string(),
+                Ok(str) => return (str.to_string(), len),
                 Err(_) => end -= 1,
             }
         }
 
-        String::from("")
+        (String::from(""), len)
     }
 
     pub fn pos(&self) -> usize {
@@ -71,4 +174,74 @@ impl Reader {
     pub fn set_pos(&mut self, n: usize) {
         self.position = n;
     }
+
+    /// Move to `n`, the end of a fixed size `record` being parsed.
+    /// With the `offset-check` feature, a parser having read past the
+    /// end of its record is reported, and asserted against in debug
+    /// builds, as it hints at a wrong layout.
+    pub fn resync(&mut self, n: usize, record: &str) {
+        #[cfg(feature = "offset-check")]
+        if self.position > n {
+            eprintln!(
+                "warning: {} parser read {} bytes past its end (0x{:X})",
+                record,
+                self.position - n,
+                n
+            );
+            debug_assert!(self.position <= n, "{} over-read", record);
+        }
+        #[cfg(not(feature = "offset-check"))]
+        let _ = record;
+
+        self.position = n;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::reader::*;
+
+    #[test]
+    fn read_string_len_full_field() {
+        let mut reader = Reader::new(b"ABCDEFGHIJKLM".to_vec());
+        assert_eq!(reader.read_string_len(12), ("ABCDEFGHIJKL".to_string(), 12));
+        assert_eq!(reader.pos(), 12);
+    }
+
+    #[test]
+    fn read_string_len_terminated() {
+        let mut reader = Reader::new(b"KICK\0\xFF\xFF\xFF\xFF\xFF\xFF\xFF".to_vec());
+        assert_eq!(reader.read_string_len(12), ("KICK".to_string(), 4));
+        assert_eq!(reader.pos(), 12);
+    }
+
+    #[test]
+    fn sub_reader_is_bounded() {
+        let reader = Reader::new(vec![0, 1, 2, 3, 4, 5]);
+        let mut sub = reader.sub(2, 3);
+        assert_eq!(sub.len(), 3);
+        assert_eq!(sub.read_bytes(3), &[2, 3, 4]);
+
+        let overrun = std::panic::catch_unwind(move || sub.read());
+        assert!(overrun.is_err());
+
+        let mut nested = reader.sub(1, 4).sub(2, 10);
+        assert_eq!(nested.len(), 2);
+        assert_eq!(nested.read(), 3);
+    }
+
+    #[test]
+    fn reader_can_move_to_another_thread() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Reader>();
+    }
+
+    #[test]
+    fn read_signed_is_centered_on_0x80() {
+        let mut reader = Reader::new(vec![0x80, 0x00, 0xFF, 0x90]);
+        assert_eq!(reader.read_signed(), 0);
+        assert_eq!(reader.read_signed(), -128);
+        assert_eq!(reader.read_signed(), 127);
+        assert_eq!(reader.read_signed(), 16);
+    }
 }
@@ -14,7 +14,8 @@ pub struct Scale {
 }
 
 impl Scale {
-    const SIZE: usize = 32;
+    /// Note enable map, 12 offsets and the name
+    pub(crate) const SIZE: usize = 2 + 12 * 2 + 16;
 
     pub fn read(reader: &mut impl std::io::Read) -> M8Result<Self> {
         let mut buf: Vec<u8> = vec![];
@@ -23,11 +24,12 @@ impl Scale {
         let mut reader = Reader::new(buf);
 
         if len < Self::SIZE + Version::SIZE {
-            return Err(ParseError(
-                "File is not long enough to be a M8 Scale".to_string(),
+            return Err(ParseError::new(
+                ParseErrorKind::TooShort,
+                "File is not long enough to be a M8 Scale",
             ));
         }
-        Version::from_reader(&mut reader)?;
+        read_header(&mut reader)?;
         Self::from_reader(&mut reader, 0)
     }
 
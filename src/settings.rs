@@ -1,4 +1,4 @@
-use crate::{reader::*, Version};
+use crate::{reader::*, ControlChange, Version};
 
 #[derive(PartialEq, Debug, Clone)]
 pub struct MidiSettings {
@@ -82,10 +82,10 @@ impl MixerSettings {
             AnalogInputSettings::Stereo(analog_input_l)
         } else {
             let analog_input_r = InputMixerSettings {
-                volume: analog_input_volume.0,
-                chorus: analog_input_chorus.0,
-                delay: analog_input_delay.0,
-                reverb: analog_input_reverb.0,
+                volume: analog_input_volume.1,
+                chorus: analog_input_chorus.1,
+                delay: analog_input_delay.1,
+                reverb: analog_input_reverb.1,
             };
             AnalogInputSettings::DualMono((analog_input_l, analog_input_r))
         };
@@ -115,6 +115,40 @@ impl MixerSettings {
             dj_filter_type,
         })
     }
+
+    /// Value of the setting driven by the given mixer FX command
+    /// ("VMV", "VT1", "DJC"...), None if the command is not a mixer
+    /// one or doesn't apply (second input channel of a stereo input).
+    pub fn command_value(&self, command: &str) -> Option<u8> {
+        let (input_l, input_r) = match &self.analog_input {
+            AnalogInputSettings::Stereo(l) => (l, None),
+            AnalogInputSettings::DualMono((l, r)) => (l, Some(r)),
+        };
+
+        match command {
+            "VMV" => Some(self.master_volume),
+            "VCH" => Some(self.chorus_volume),
+            "VDE" => Some(self.delay_volume),
+            "VRE" => Some(self.reverb_volume),
+            "VT1" | "VT2" | "VT3" | "VT4" | "VT5" | "VT6" | "VT7" | "VT8" => {
+                let track = (command.as_bytes()[2] - b'1') as usize;
+                Some(self.track_volume[track])
+            }
+            "DJC" => Some(self.dj_filter),
+            "DJR" => Some(self.dj_peak),
+            "DJT" => Some(self.dj_filter_type),
+            "VIN" => Some(input_l.volume),
+            "ICH" => Some(input_l.chorus),
+            "IDE" => Some(input_l.delay),
+            "IRE" => Some(input_l.reverb),
+            "VI2" => input_r.map(|r| r.volume),
+            "IC2" => input_r.map(|r| r.chorus),
+            "ID2" => input_r.map(|r| r.delay),
+            "IR2" => input_r.map(|r| r.reverb),
+            "USB" => Some(self.usb_input.volume),
+            _ => None,
+        }
+    }
 }
 
 #[derive(PartialEq, Debug, Clone)]
@@ -209,6 +243,84 @@ impl EffectsSettings {
             reverb_width,
         })
     }
+
+    /// Value of the setting driven by the given effect FX command
+    /// ("XCM", "XDF", "XRS"...), None if the command is not an effect
+    /// one or is not stored in the song settings.
+    pub fn command_value(&self, command: &str) -> Option<u8> {
+        match command {
+            "XCM" => Some(self.chorus_mod_depth),
+            "XCF" => Some(self.chorus_mod_freq),
+            "XCR" => Some(self.chorus_reverb_send),
+            // the command drives both delay times, report the left one
+            "XDT" => Some(self.delay_time_l),
+            "XDF" => Some(self.delay_feedback),
+            "XDW" => Some(self.delay_width),
+            "XDR" => Some(self.delay_reverb_send),
+            "XRS" => Some(self.reverb_size),
+            "XRD" => Some(self.reverb_damping),
+            "XRM" => Some(self.reverb_mod_depth),
+            "XRF" => Some(self.reverb_mod_freq),
+            "XRW" => Some(self.reverb_width),
+            _ => None,
+        }
+    }
+}
+
+/// Node of the effect signal path
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum EffectBus {
+    Chorus,
+    Delay,
+    Reverb,
+    Master,
+}
+
+/// Level of the signal sent from a bus to another one
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct EffectSend {
+    pub from: EffectBus,
+    pub to: EffectBus,
+    pub level: u8,
+}
+
+/// Send topology of the global effects: the chorus feeds the reverb,
+/// the delay feeds the reverb, and every effect returns to the master
+/// with its mixer volume.
+#[derive(PartialEq, Debug, Clone)]
+pub struct EffectRouting {
+    pub sends: Vec<EffectSend>,
+}
+
+impl EffectRouting {
+    pub fn new(mixer: &MixerSettings, effects: &EffectsSettings) -> Self {
+        let send = |from, to, level| EffectSend { from, to, level };
+        Self {
+            sends: vec![
+                send(
+                    EffectBus::Chorus,
+                    EffectBus::Reverb,
+                    effects.chorus_reverb_send,
+                ),
+                send(
+                    EffectBus::Delay,
+                    EffectBus::Reverb,
+                    effects.delay_reverb_send,
+                ),
+                send(EffectBus::Chorus, EffectBus::Master, mixer.chorus_volume),
+                send(EffectBus::Delay, EffectBus::Master, mixer.delay_volume),
+                send(EffectBus::Reverb, EffectBus::Master, mixer.reverb_volume),
+            ],
+        }
+    }
+
+    /// Level sent from a bus to another, None if they are not connected
+    pub fn send(&self, from: EffectBus, to: EffectBus) -> Option<u8> {
+        self.sends
+            .iter()
+            .find(|s| s.from == from && s.to == to)
+            .map(|s| s.level)
+    }
 }
 
 #[derive(PartialEq, Debug, Clone)]
@@ -238,4 +350,12 @@ impl MidiMapping {
     pub fn empty(&self) -> bool {
         self.channel == 0
     }
+
+    /// Incoming CC (number and last value) driving this mapping
+    pub fn control_change(&self) -> ControlChange {
+        ControlChange {
+            number: self.control_number,
+            value: self.value,
+        }
+    }
 }
@@ -11,7 +11,7 @@ use crate::remapper::TableMapping;
 use crate::scale::*;
 use crate::settings::*;
 use crate::version::*;
-use crate::writer::Writer;
+use crate::writer::{fnv1a, Writer, FNV_OFFSET};
 
 use arr_macro::arr;
 use byteorder::{ByteOrder, LittleEndian};
@@ -37,6 +37,16 @@ pub struct Offsets {
 }
 
 impl Offsets {
+    /// Layout of a song file of the given version. Files before 4.0
+    /// share the 4.0 section offsets (up to the EQs they lack).
+    pub fn for_version(ver: Version) -> &'static Offsets {
+        if ver.at_least(4, 1) {
+            &V4_1_OFFSETS
+        } else {
+            &V4_OFFSETS
+        }
+    }
+
     pub fn eq_count(&self) -> usize {
         // general EQ + 3 for effects + 1 global
         self.instrument_eq_count + 3 + 1
@@ -73,6 +83,23 @@ pub const V4_1_OFFSETS: Offsets = Offsets {
     instrument_file_eq_offset: Some(0x165),
 };
 
+/// Location of the sections of a song file. M8 files carry no table
+/// of content, the layout is selected from the version of the header.
+#[derive(Clone, Copy)]
+pub struct Toc {
+    pub version: Version,
+    pub offsets: &'static Offsets,
+}
+
+/// Read the header of a song file and locate its sections
+pub fn read_toc(bytes: &[u8]) -> M8Result<Toc> {
+    let version = read_header(&mut Reader::new(bytes.to_vec()))?.version;
+    Ok(Toc {
+        version,
+        offsets: Offsets::for_version(version),
+    })
+}
+
 ////////////////////////////////////////////////////////////////////////////////////
 // MARK: Song
 ////////////////////////////////////////////////////////////////////////////////////
@@ -83,6 +110,10 @@ pub struct Song {
     pub transpose: u8,
     pub tempo: f32,
     pub quantize: u8,
+
+    /// Song name, 12 characters at most. The song format has no
+    /// dedicated region for free text project notes, this name is the
+    /// only textual description stored in the file.
     pub name: String,
     pub key: u8,
 
@@ -129,8 +160,29 @@ impl fmt::Debug for Song {
     }
 }
 
+/// Container of a FX found by [`Song::iter_fx`]
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum FxSource {
+    Phrase(usize),
+    Table(usize),
+}
+
+/// A FX of the song with its position
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct FxLocation {
+    pub source: FxSource,
+
+    /// Step in the phrase or table, from 0 to 15
+    pub step: usize,
+
+    /// FX column, from 0 (FX1) to 2 (FX3)
+    pub column: usize,
+
+    pub fx: FX,
+}
+
 impl Song {
-    const SIZE_PRIOR_TO_2_5: usize = 0x1A970;
+    pub(crate) const SIZE_PRIOR_TO_2_5: usize = 0x1A970;
     const SIZE: usize = 0x1AD09;
     pub const N_PHRASES: usize = 255;
     pub const N_CHAINS: usize = 255;
@@ -149,17 +201,162 @@ impl Song {
     }
 
     pub fn offsets(&self) -> &'static Offsets {
-        if self.version.at_least(4, 1) {
-            &V4_1_OFFSETS
-        } else {
-            &V4_OFFSETS
-        }
+        Offsets::for_version(self.version)
     }
 
     pub fn eq_count(&self) -> usize {
         self.offsets().eq_count()
     }
 
+    /// Hash of the musical content of the song (arrangement, chains,
+    /// phrases, tables, grooves and normalized instruments), to tell if
+    /// two files are functionally identical despite byte differences.
+    /// The hash (FNV-1a of the written content) is stable across runs
+    /// and builds.
+    pub fn content_hash(&self) -> u64 {
+        let hash = fnv1a(FNV_OFFSET, &self.tempo.to_le_bytes());
+        let hash = fnv1a(hash, &self.song.steps);
+
+        let mut w = Writer::with_capacity(
+            self.chains.len() * Chain::V4_SIZE
+                + self.phrases.len() * Phrase::V4_SIZE
+                + self.tables.len() * Table::V4_SIZE
+                + self.grooves.len() * 16,
+        );
+        for ch in &self.chains {
+            ch.write(&mut w);
+        }
+        for ph in &self.phrases {
+            ph.write(&mut w);
+        }
+        for table in &self.tables {
+            table.write(&mut w);
+        }
+        for groove in &self.grooves {
+            groove.write(&mut w);
+        }
+        let hash = fnv1a(hash, w.as_slice());
+
+        self.instruments.iter().fold(hash, |hash, instr| {
+            fnv1a(hash, &instr.content_hash(self.version).to_le_bytes())
+        })
+    }
+
+    /// Lazily iterate over every non empty FX of the phrases then of
+    /// the tables of the song, with their location.
+    pub fn iter_fx(&self) -> impl Iterator<Item = FxLocation> + '_ {
+        let phrases = self.phrases.iter().enumerate().flat_map(|(ix, phrase)| {
+            phrase
+                .steps
+                .iter()
+                .map(|s| s.all_fx())
+                .enumerate()
+                .map(move |(step, fxs)| (FxSource::Phrase(ix), step, fxs))
+        });
+
+        let tables = self.tables.iter().enumerate().flat_map(|(ix, table)| {
+            table
+                .steps
+                .iter()
+                .map(|s| s.all_fx())
+                .enumerate()
+                .map(move |(step, fxs)| (FxSource::Table(ix), step, fxs))
+        });
+
+        phrases
+            .chain(tables)
+            .flat_map(|(source, step, fxs)| {
+                fxs.into_iter()
+                    .enumerate()
+                    .map(move |(column, fx)| FxLocation {
+                        source,
+                        step,
+                        column,
+                        fx,
+                    })
+            })
+            .filter(|loc| !loc.fx.is_empty())
+    }
+
+    /// Every non empty FX of the song matching `pred`, e.g. the `DEL`
+    /// commands with a value above 0x40, in [`Song::iter_fx`] order.
+    pub fn find_fx(&self, pred: impl Fn(&FX) -> bool) -> Vec<FxLocation> {
+        self.iter_fx().filter(|loc| pred(&loc.fx)).collect()
+    }
+
+    /// Signal path of the global effects, with their send levels
+    pub fn effect_routing(&self) -> EffectRouting {
+        EffectRouting::new(&self.mixer_settings, &self.effects_settings)
+    }
+
+    /// Value of the global mixer or effect setting addressed by a
+    /// mixer FX command name, as listed in the V4 mixer commands.
+    pub fn mixer_command_value(&self, command: &str) -> Option<u8> {
+        self.mixer_settings
+            .command_value(command)
+            .or_else(|| self.effects_settings.command_value(command))
+    }
+
+    /// Instrument referenced by a phrase step, None if the slot is empty
+    /// or out of the instrument bank (like the 0xFF "no instrument").
+    pub fn instrument_for(&self, index: u8) -> Option<&Instrument> {
+        self.instruments
+            .get(index as usize)
+            .filter(|instr| !instr.is_empty())
+    }
+
+    /// Table played by an instrument: instrument N owns table N, the
+    /// tables above the instrument count being free standing ones.
+    /// None for an index out of the instrument bank.
+    pub fn table_for_instrument(&self, instr_index: u8) -> Option<&Table> {
+        if (instr_index as usize) < Song::N_INSTRUMENTS {
+            self.tables.get(instr_index as usize)
+        } else {
+            None
+        }
+    }
+
+    /// Groove a phrase runs under when nothing selects another one. The
+    /// file stores no per song or per chain assignment: every track
+    /// starts on groove 0 and only the `GRV` command changes it (see
+    /// [`Phrase::groove_override`]).
+    pub fn default_groove(&self) -> &Groove {
+        &self.grooves[0]
+    }
+
+    /// Starting tempo of the song in BPM, before any `TPO` command
+    pub fn tempo(&self) -> f32 {
+        self.tempo
+    }
+
+    /// Global transpose of the song in semitones, stored like the
+    /// chain transpose (0xF4 being -12). `TSP` commands move it while
+    /// playing.
+    pub fn transpose(&self) -> i8 {
+        self.transpose as i8
+    }
+
+    /// Real time length in seconds of each step of a phrase played with
+    /// the given groove, at `bpm` beats per minute (use `self.tempo` for
+    /// the song tempo). The M8 runs at 24 ticks per beat, the groove
+    /// giving the number of ticks of each step in turn.
+    pub fn step_durations(&self, groove: &Groove, bpm: f32) -> Vec<f32> {
+        let tick = 60.0 / (bpm * Groove::TICKS_PER_BEAT as f32);
+        let ticks = groove.active_steps();
+        let ticks = if ticks.is_empty() {
+            &[Groove::DEFAULT_TICKS][..]
+        } else {
+            ticks
+        };
+
+        ticks
+            .iter()
+            .cycle()
+            .take(Phrase::STEP_COUNT)
+            .map(|&t| t as f32 * tick)
+            .collect()
+    }
+
     pub fn table_view(&self, ix: usize) -> TableView {
         TableView {
             table: &self.tables[ix],
@@ -184,14 +381,16 @@ impl Song {
 
     pub fn read_from_reader(mut reader: &mut Reader) -> M8Result<Self> {
         if reader.len() < Self::SIZE_PRIOR_TO_2_5 + Version::SIZE {
-            return Err(ParseError(
-                "File is not long enough to be a M8 song".to_string(),
+            return Err(ParseError::new(
+                ParseErrorKind::TooShort,
+                "File is not long enough to be a M8 song",
             ));
         }
-        let version = Version::from_reader(&mut reader)?;
+        let version = read_header(&mut reader)?.version;
         if version.at_least(2, 5) && reader.len() < Self::SIZE + Version::SIZE {
-            return Err(ParseError(
-                "File is not long enough to be a M8 song".to_string(),
+            return Err(ParseError::new(
+                ParseErrorKind::TooShort,
+                "File is not long enough to be a M8 song",
             ));
         }
 
@@ -232,7 +431,7 @@ impl Song {
         for instr in &self.instruments {
             let pos = w.pos();
             instr.write(self.version, w);
-            w.seek(pos + Instrument::INSTRUMENT_MEMORY_SIZE);
+            w.seek(pos + Instrument::bank_stride(self.version));
         }
 
         w.seek(ofs.eq);
@@ -294,11 +493,7 @@ impl Song {
         };
 
         let eqs = if version.at_least(4, 0) {
-            let ofs = if version.at_least(4, 1) {
-                &V4_1_OFFSETS
-            } else {
-                &V4_OFFSETS
-            };
+            let ofs = Offsets::for_version(version);
 
             reader.set_pos(ofs.eq);
             (0..ofs.instrument_eq_count)
@@ -408,6 +603,14 @@ impl Chain {
         }
     }
 
+    /// Signed transpose in semitones of the chain `row`, as stored in
+    /// its TSP column (0xFE being -2). It adds up to the song transpose
+    /// and is applied to the notes of the row phrase by
+    /// [`effective_note`], only for instruments with TRANSPOSE on.
+    pub fn transpose_at(&self, row: usize) -> i8 {
+        self.steps[row].transpose as i8
+    }
+
     pub fn print_screen(&self) -> String {
         (0..16).fold("  PH TSP\n".to_string(), |s, row| {
             s + &self.steps[row].print(row as u8) + "\n"
@@ -516,6 +719,7 @@ pub struct Phrase {
 
 impl Phrase {
     pub const V4_SIZE: usize = 16 * Step::V4_SIZE;
+    pub const STEP_COUNT: usize = 16;
 
     pub fn is_empty(&self) -> bool {
         self.steps.iter().all(|s| s.is_empty())
@@ -527,6 +731,16 @@ impl Phrase {
         }
     }
 
+    /// Groove selected by the first `GRV` command of the phrase, if any.
+    pub fn groove_override(&self) -> Option<u8> {
+        let grv = FX::fx_command_names(self.version).find_indices(&["GRV"]);
+        self.steps
+            .iter()
+            .flat_map(|s| [s.fx1, s.fx2, s.fx3])
+            .find(|fx| grv.contains(&fx.command))
+            .map(|fx| fx.value)
+    }
+
     pub fn print_screen(&self, instruments: &[Instrument]) -> String {
         let mut cmd_pack = CommandPack::default();
         let fx_commands = FX::fx_command_names(self.version);
@@ -547,6 +761,47 @@ impl Phrase {
         acc
     }
 
+    /// Set the FX of column `col` (0 to 2) of a step from its command
+    /// mnemonic, looked up in the sequencer commands then in the
+    /// commands of the instrument.
+    pub fn set_fx(
+        &mut self,
+        step: usize,
+        col: usize,
+        mnemonic: &str,
+        value: u8,
+        fx: FxCommands,
+        pack: CommandPack,
+    ) -> M8Result<()> {
+        if step >= Phrase::STEP_COUNT || col >= 3 {
+            return Err(ParseError::new(
+                ParseErrorKind::InvalidValue(step.min(0xFF) as u8),
+                format!("Invalid FX position step {} column {}", step, col),
+            ));
+        }
+
+        let command = fx
+            .index_map()
+            .get(mnemonic)
+            .copied()
+            .or_else(|| pack.find_command(mnemonic))
+            .ok_or_else(|| {
+                ParseError::new(
+                    ParseErrorKind::UnknownCommand,
+                    format!("Unknown command {}", mnemonic),
+                )
+            })?;
+
+        let s = &mut self.steps[step];
+        let target = match col {
+            0 => &mut s.fx1,
+            1 => &mut s.fx2,
+            _ => &mut s.fx3,
+        };
+        *target = FX { command, value };
+        Ok(())
+    }
+
     pub fn map_instruments(
         &self,
         instrument_mapping: &InstrumentMapping,
@@ -719,6 +974,20 @@ impl Note {
     }
 }
 
+/// Note played for `note` by the instrument, `transpose` being the
+/// transpose in semitones applied by the chain and song (see
+/// [`Chain::transpose_at`] and [`Song::transpose`]). The transpose is
+/// only applied when the instrument has its TRANSPOSE flag on, the
+/// result being clamped to the playable range. Empty and OFF notes are
+/// returned unchanged.
+pub fn effective_note(note: u8, instr: &Instrument, transpose: i8) -> u8 {
+    if note >= 0x80 || !instr.transpose() {
+        return note;
+    }
+
+    (note as i16 + transpose as i16).clamp(0, 0x7F) as u8
+}
+
 impl Default for Note {
     fn default() -> Self {
         Note(255)
@@ -764,6 +1033,14 @@ pub struct Table {
 impl Table {
     pub const V4_SIZE: usize = 16 * TableStep::V4_SIZE;
 
+    /// Table without any step set
+    pub fn empty(version: Version) -> Self {
+        Self {
+            steps: arr![TableStep::default(); 16],
+            version,
+        }
+    }
+
     pub fn is_empty(&self) -> bool {
         self.steps.iter().all(|s| s.is_empty())
     }
@@ -940,6 +1217,12 @@ pub struct Groove {
     pub steps: [u8; 16],
 }
 impl Groove {
+    /// Number of ticks in a beat (a quarter note)
+    pub const TICKS_PER_BEAT: usize = 24;
+
+    /// Ticks per step of the default groove
+    pub const DEFAULT_TICKS: u8 = 6;
+
     fn from_reader(reader: &mut Reader, number: u8) -> M8Result<Self> {
         Ok(Self {
             number,
@@ -1189,4 +1472,322 @@ mod tests {
         assert_eq!(test_file.transpose, 0x0C);
         assert_eq!(test_file.quantize, 0x02);
     }
+
+    #[test]
+    fn empty_slots_rewrite_unchanged() {
+        let bytes = std::fs::read("./examples/songs/V4EMPTY.m8s").expect("Could not open V4EMPTY");
+        let song = Song::read(&mut &bytes[..]).unwrap();
+
+        let mut w = Writer::new(bytes.clone());
+        song.write(&mut w).unwrap();
+        assert!(w.finish() == bytes);
+    }
+
+    #[test]
+    fn song_tempo_and_transpose() {
+        let mut song = test_file();
+        assert_eq!(song.tempo(), 120.0);
+        assert_eq!(song.transpose(), 12);
+
+        song.transpose = 0xF4;
+        assert_eq!(song.transpose(), -12);
+    }
+
+    #[test]
+    fn test_uniform_groove_step_durations() {
+        let song = test_file();
+        let groove = Groove {
+            number: 0,
+            steps: [6; 16],
+        };
+        let durations = song.step_durations(&groove, 120.0);
+        assert_eq!(durations.len(), 16);
+        assert!(durations.iter().all(|&d| (d - 0.125).abs() < 1e-6));
+    }
+
+    #[test]
+    fn test_swing_groove_step_durations() {
+        let song = test_file();
+        let mut steps = [0xFF; 16];
+        steps[0] = 8;
+        steps[1] = 4;
+        let groove = Groove { number: 1, steps };
+        let durations = song.step_durations(&groove, 120.0);
+        assert_eq!(durations.len(), 16);
+        assert!((durations[0] - 1.0 / 6.0).abs() < 1e-6);
+        assert!((durations[1] - 1.0 / 12.0).abs() < 1e-6);
+        assert!((durations[14] - durations[0]).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_mixer_command_values() {
+        let mut f = File::open("./examples/songs/Bundle/FDUB3.m8s").expect("Could not open FDUB3");
+        let song = Song::read(&mut f).expect("Could not parse FDUB3");
+
+        assert_eq!(song.mixer_command_value("VMV"), Some(0xE0));
+        assert_eq!(song.mixer_command_value("VDE"), Some(0xC0));
+        assert_eq!(song.mixer_command_value("VT8"), Some(0xE0));
+        assert_eq!(song.mixer_command_value("DJC"), Some(0x80));
+        assert_eq!(song.mixer_command_value("XCM"), Some(0x40));
+        assert_eq!(song.mixer_command_value("XCF"), Some(0x80));
+        assert_eq!(song.mixer_command_value("VI2"), None);
+        assert_eq!(song.mixer_command_value("ARP"), None);
+        assert_eq!(song.mixer_settings.master_limit, 0x20);
+    }
+
+    #[test]
+    fn test_content_hash_ignores_off_mods() {
+        let song = test_file();
+        let mut resaved = test_file();
+        assert_eq!(song.content_hash(), resaved.content_hash());
+
+        // stale bytes in a modulator without destination
+        let params = resaved.instruments[4].synth_params_mut().unwrap();
+        assert_eq!(params.mods[3].dest(), 0);
+        match &mut params.mods[3] {
+            Mod::LFO(lfo) => {
+                lfo.freq = 0x34;
+                lfo.amount = 0x12;
+            }
+            other => panic!("Expected an LFO, got {:?}", other),
+        }
+        assert_eq!(song.content_hash(), resaved.content_hash());
+
+        resaved.phrases[0].steps[0].velocity = 0x10;
+        assert_ne!(song.content_hash(), resaved.content_hash());
+    }
+
+    #[test]
+    fn test_midi_settings_reading() {
+        let mut f = File::open("./examples/songs/Bundle/FDUB3.m8s").expect("Could not open FDUB3");
+        let song = Song::read(&mut f).expect("Could not parse FDUB3");
+
+        let midi = &song.midi_settings;
+        assert!(!midi.receive_sync);
+        assert!(midi.send_sync);
+        assert_eq!(midi.send_transport, 1);
+        assert_eq!(midi.record_note_channel, 9);
+        assert_eq!(midi.control_map_channel, 10);
+        assert_eq!(midi.song_row_cue_channel, 11);
+        assert_eq!(midi.track_input_channel, [0, 0, 0, 1, 1, 1, 0, 0]);
+        assert_eq!(midi.track_input_intrument[3], 0x70);
+        assert_eq!(midi.track_input_mode, 2);
+
+        let mappings: Vec<&MidiMapping> =
+            song.midi_mappings.iter().filter(|m| !m.empty()).collect();
+        assert_eq!(mappings.len(), 7);
+        assert_eq!(
+            mappings[0].control_change(),
+            ControlChange {
+                number: 10,
+                value: 25
+            }
+        );
+        assert_eq!((mappings[0].min_value, mappings[0].max_value), (0x40, 0xBF));
+    }
+
+    #[test]
+    fn iter_fx_finds_del() {
+        let mut song = test_file();
+        for phrase in &mut song.phrases {
+            phrase.clear();
+        }
+        for table in &mut song.tables {
+            table.clear();
+        }
+
+        let del = FX::fx_command_names(song.version).find_indices(&["DEL"])[0];
+        song.phrases[3].steps[5].fx2 = FX {
+            command: del,
+            value: 0x20,
+        };
+
+        let found: Vec<FxLocation> = song.iter_fx().collect();
+        assert_eq!(
+            found,
+            vec![FxLocation {
+                source: FxSource::Phrase(3),
+                step: 5,
+                column: 1,
+                fx: FX {
+                    command: del,
+                    value: 0x20
+                },
+            }]
+        );
+    }
+
+    #[test]
+    fn find_fx_matches_command_any_value() {
+        let mut song = test_file();
+        for phrase in &mut song.phrases {
+            phrase.clear();
+        }
+        for table in &mut song.tables {
+            table.clear();
+        }
+
+        let cmds = FX::fx_command_names(song.version);
+        let kil = cmds.find_indices(&["KIL"])[0];
+        let del = cmds.find_indices(&["DEL"])[0];
+        song.phrases[1].steps[0].fx1 = FX {
+            command: kil,
+            value: 0x00,
+        };
+        song.phrases[1].steps[2].fx1 = FX {
+            command: del,
+            value: 0x03,
+        };
+        song.tables[7].steps[4].fx3 = FX {
+            command: kil,
+            value: 0x05,
+        };
+
+        let found = song.find_fx(|fx| fx.command == kil);
+        let places: Vec<(FxSource, usize, u8)> = found
+            .iter()
+            .map(|loc| (loc.source, loc.step, loc.fx.value))
+            .collect();
+        assert_eq!(
+            places,
+            vec![
+                (FxSource::Phrase(1), 0, 0x00),
+                (FxSource::Table(7), 4, 0x05)
+            ]
+        );
+    }
+
+    #[test]
+    fn effective_note_follows_instrument_transpose() {
+        let ver = Version::default();
+        let mut ws = WavSynth::default_for(ver);
+        ws.transpose = true;
+        let transposing = Instrument::WavSynth(ws.clone());
+        ws.transpose = false;
+        let fixed = Instrument::WavSynth(ws);
+
+        assert_eq!(effective_note(0x24, &transposing, 12), 0x30);
+        assert_eq!(effective_note(0x24, &transposing, -12), 0x18);
+        assert_eq!(effective_note(0x24, &fixed, 12), 0x24);
+        assert_eq!(effective_note(0xFF, &transposing, 12), 0xFF);
+        assert_eq!(effective_note(0x80, &transposing, 12), 0x80);
+        assert_eq!(effective_note(0x02, &transposing, -12), 0);
+    }
+
+    #[test]
+    fn toc_locates_instruments() {
+        for path in [
+            "./examples/songs/TEST-FILE.m8s",
+            "./examples/songs/Bundle/FDUB3.m8s",
+        ] {
+            let bytes = std::fs::read(path).expect("Could not open song");
+            let song = Song::read(&mut &bytes[..]).unwrap();
+            let toc = read_toc(&bytes).unwrap();
+            assert_eq!(toc.version, song.version);
+            assert_eq!(toc.offsets.instruments, 0x13A3E);
+
+            let mut reader = Reader::new(bytes);
+            reader.set_pos(toc.offsets.instruments);
+            let bank = Bank::read(&mut reader, toc.version).unwrap();
+            for (i, instr) in song.instruments.iter().enumerate() {
+                assert_eq!(bank.get(i).unwrap_or(&Instrument::None), instr);
+            }
+        }
+    }
+
+    #[test]
+    fn instrument_table_linkage() {
+        let song = test_file();
+        let table = song.table_for_instrument(3).unwrap();
+        assert!(std::ptr::eq(table, &song.tables[3]));
+        assert!(song.table_for_instrument(0x80).is_none());
+        assert!(song.table_for_instrument(0xFF).is_none());
+    }
+
+    #[test]
+    fn dangling_instrument_reference() {
+        let song = test_file();
+        let mut phrase = song.phrases[0].clone();
+        phrase.steps[0].instrument = 0x7E;
+        assert!(song.instruments[0x7E].is_empty());
+
+        assert!(song.instrument_for(phrase.steps[0].instrument).is_none());
+        assert!(song.instrument_for(0xFF).is_none());
+        assert!(matches!(
+            song.instrument_for(1),
+            Some(Instrument::WavSynth(_))
+        ));
+    }
+
+    #[test]
+    fn chain_negative_transpose() {
+        let mut chain = Chain {
+            steps: [ChainStep::default(); 16],
+        };
+        chain.steps[3] = ChainStep {
+            phrase: 0x02,
+            transpose: 0xFB,
+        };
+        assert_eq!(chain.transpose_at(0), 0);
+        assert_eq!(chain.transpose_at(3), -5);
+
+        let instr = Instrument::WavSynth(WavSynth::default_for(Version::default()));
+        assert_eq!(effective_note(45, &instr, chain.transpose_at(3)), 40);
+    }
+
+    #[test]
+    fn phrase_groove_override() {
+        let song = test_file();
+        let fx = FX::fx_command_names(song.version);
+        let pack = CommandPack::default();
+        let mut phrase = song.phrases[0].clone();
+        phrase.clear();
+        assert_eq!(phrase.groove_override(), None);
+        assert_eq!(song.default_groove().number, 0);
+
+        phrase.set_fx(6, 2, "GRV", 0x03, fx, pack).unwrap();
+        assert_eq!(phrase.groove_override(), Some(0x03));
+    }
+
+    #[test]
+    fn phrase_set_fx_by_mnemonic() {
+        let song = test_file();
+        let fx = FX::fx_command_names(song.version);
+        let pack = song.instruments[4].instr_command_text(song.version);
+        let mut phrase = song.phrases[0].clone();
+
+        phrase.set_fx(3, 1, "DEL", 0x03, fx, pack).unwrap();
+        let step = &phrase.steps[3];
+        assert_eq!(fx.try_render(step.fx2.command), Some("DEL"));
+        assert_eq!(step.fx2.value, 0x03);
+
+        phrase.set_fx(4, 0, "ALG", 0x02, fx, pack).unwrap();
+        assert_eq!(pack.try_render(phrase.steps[4].fx1.command), Some("ALG"));
+
+        let err = phrase.set_fx(3, 1, "ZZZ", 0, fx, pack).unwrap_err();
+        assert_eq!(err.kind(), ParseErrorKind::UnknownCommand);
+        assert!(phrase.set_fx(16, 0, "DEL", 0, fx, pack).is_err());
+    }
+
+    #[test]
+    fn fdub3_effect_routing() {
+        let mut f = File::open("./examples/songs/Bundle/FDUB3.m8s").expect("Could not open FDUB3");
+        let song = Song::read(&mut f).expect("Could not parse FDUB3");
+        let routing = song.effect_routing();
+
+        assert_eq!(routing.sends.len(), 5);
+        assert_eq!(
+            routing.send(EffectBus::Delay, EffectBus::Master),
+            Some(0xC0)
+        );
+        assert_eq!(
+            routing.send(EffectBus::Delay, EffectBus::Reverb),
+            Some(song.effects_settings.delay_reverb_send)
+        );
+        assert_eq!(
+            routing.send(EffectBus::Chorus, EffectBus::Reverb),
+            Some(song.effects_settings.chorus_reverb_send)
+        );
+        assert_eq!(routing.send(EffectBus::Reverb, EffectBus::Delay), None);
+    }
 }
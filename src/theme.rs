@@ -18,7 +18,7 @@ pub struct Theme {
     pub meter_peak: RGB,
 }
 impl Theme {
-    const SIZE: usize = 39;
+    pub(crate) const SIZE: usize = 39;
 
     pub fn read(reader: &mut impl std::io::Read) -> M8Result<Self> {
         let mut buf: Vec<u8> = vec![];
@@ -27,11 +27,12 @@ impl Theme {
         let mut reader = Reader::new(buf);
 
         if len < Self::SIZE + Version::SIZE {
-            return Err(ParseError(
-                "File is not long enough to be a M8 Theme".to_string(),
+            return Err(ParseError::new(
+                ParseErrorKind::TooShort,
+                "File is not long enough to be a M8 Theme",
             ));
         }
-        Version::from_reader(&mut reader)?;
+        read_header(&mut reader)?;
         Self::from_reader(&mut reader)
     }
 
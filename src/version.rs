@@ -34,22 +34,25 @@ impl fmt::Debug for Version {
 impl Version {
     pub const SIZE: usize = 14;
 
+    /// Write a complete file header (magic included) for this version,
+    /// as found at the start of songs, `Version::SIZE` bytes.
     pub fn write(&self, w: &mut Writer) {
-        w.write(self.major);
-        w.write((self.minor << 4) | self.patch);
-        w.write(0);
-        w.write(0);
+        Header::new(*self).write(w)
     }
 
+    /// Read the version of a file header, checking the magic string
+    /// and that the header is complete.
     pub fn from_reader(reader: &mut Reader) -> M8Result<Self> {
-        let _version_string = reader.read_bytes(10);
+        Ok(read_header(reader)?.version)
+    }
+
+    fn read_numbers(reader: &mut Reader) -> M8Result<Self> {
         let lsb = reader.read();
         let msb = reader.read();
         let major = msb & 0x0F;
         let minor = (lsb >> 4) & 0x0F;
         let patch = lsb & 0x0F;
 
-        reader.read_bytes(2); // Skip
         Ok(Self {
             major,
             minor,
@@ -61,3 +64,130 @@ impl Version {
         self.major > major || (self.major == major && self.minor >= minor)
     }
 }
+
+/// Preamble shared by every M8 file kind (song, instrument, theme,
+/// scale...): the "M8VERSION" magic string followed by the version.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct Header {
+    pub version: Version,
+
+    /// Bytes following the version, zero in songs but not in the
+    /// instrument files saved by the device. Kept to rewrite a read
+    /// header identically.
+    pub trailer: [u8; 2],
+}
+
+impl Header {
+    pub const MAGIC: &'static [u8; 10] = b"M8VERSION\0";
+
+    pub fn new(version: Version) -> Self {
+        Self {
+            version,
+            trailer: [0; 2],
+        }
+    }
+
+    /// Header of the instrument files (.m8i) saved by the device,
+    /// whose trailer is `00 10`.
+    pub fn for_instrument(version: Version) -> Self {
+        Self {
+            version,
+            trailer: [0x00, 0x10],
+        }
+    }
+
+    /// Write the magic and the version, `Version::SIZE` bytes
+    pub fn write(&self, w: &mut Writer) {
+        let v = self.version;
+        w.write_bytes(Header::MAGIC);
+        w.write((v.minor << 4) | (v.patch & 0x0F));
+        w.write(v.major);
+        w.write_bytes(&self.trailer);
+    }
+}
+
+/// Read the file preamble, checking the magic string before
+/// returning the version of the file.
+pub fn read_header(reader: &mut Reader) -> M8Result<Header> {
+    if reader.len() < reader.pos() + Version::SIZE {
+        return Err(ParseError::new(
+            ParseErrorKind::TooShort,
+            "File is not long enough to hold a M8 header",
+        ));
+    }
+
+    if reader.read_bytes(Header::MAGIC.len()) != Header::MAGIC {
+        return Err(ParseError::new(
+            ParseErrorKind::InvalidMagic,
+            "Not a M8 file, missing M8VERSION magic",
+        ));
+    }
+
+    let version = Version::read_numbers(reader)?;
+    let trailer = [reader.read(), reader.read()];
+    Ok(Header { version, trailer })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::version::*;
+
+    #[test]
+    fn corrupted_magic_is_rejected() {
+        let mut file = std::fs::read("./examples/instruments/FMDUBSTAB_4_1.m8i")
+            .expect("Could not open FMDUBSTAB_4_1");
+        let header = read_header(&mut Reader::new(file.clone())).unwrap();
+        assert!(header.version.at_least(4, 1));
+
+        file[2] = b'X';
+        let err = read_header(&mut Reader::new(file.clone())).unwrap_err();
+        assert_eq!(err.kind(), ParseErrorKind::InvalidMagic);
+        assert!(crate::Instrument::read(&mut &file[..]).is_err());
+    }
+
+    #[test]
+    fn headers_are_rewritten_identically() {
+        let files = [
+            "./examples/songs/DEFAULT.m8s",
+            "./examples/songs/TEST-FILE.m8s",
+            "./examples/songs/V4EMPTY.m8s",
+            "./examples/instruments/FMDUBSTAB_4_0.m8i",
+            "./examples/instruments/FMDUBSTAB_4_1.m8i",
+        ];
+
+        let mut majors = Vec::new();
+        for path in files {
+            let file = std::fs::read(path).expect("Could not open example file");
+            let original = &file[..Version::SIZE];
+            let header = read_header(&mut Reader::new(file.clone())).unwrap();
+            majors.push(header.version.major);
+
+            let mut w = Writer::with_capacity(Version::SIZE);
+            header.write(&mut w);
+            assert_eq!(w.finish(), original, "{}", path);
+
+            if header.trailer == [0, 0] {
+                let mut w = Writer::with_capacity(Version::SIZE);
+                header.version.write(&mut w);
+                assert_eq!(w.finish(), original, "{}", path);
+            }
+        }
+        assert_eq!(majors, vec![2, 3, 4, 3, 4]);
+    }
+
+    #[test]
+    fn version_reader_checks_magic_and_length() {
+        let mut header = b"M8VERSION\0".to_vec();
+        header.extend([0x12, 0x04, 0, 0]);
+        let ver = Version::from_reader(&mut Reader::new(header.clone())).unwrap();
+        assert_eq!((ver.major, ver.minor, ver.patch), (4, 1, 2));
+
+        let mut wrong = header.clone();
+        wrong[0] = b'X';
+        let err = Version::from_reader(&mut Reader::new(wrong)).unwrap_err();
+        assert_eq!(err.kind(), ParseErrorKind::InvalidMagic);
+
+        let err = Version::from_reader(&mut Reader::new(header[..12].to_vec())).unwrap_err();
+        assert_eq!(err.kind(), ParseErrorKind::TooShort);
+    }
+}
@@ -9,17 +9,45 @@ impl Writer {
         Writer { buffer: v, pos: 0 }
     }
 
+    /// Initialize an empty writer, preallocating `n` bytes. Writing
+    /// past the end of the buffer extends it.
+    pub fn with_capacity(n: usize) -> Writer {
+        Writer {
+            buffer: Vec::with_capacity(n),
+            pos: 0,
+        }
+    }
+
     /// Terminate writing and return the buffer
     pub fn finish(self) -> Vec<u8> {
         self.buffer
     }
 
+    /// Bytes written so far (including the preloaded ones)
+    pub fn as_slice(&self) -> &[u8] {
+        &self.buffer
+    }
+
+    /// Make sure the buffer is at least `end` bytes long, zero filling it.
+    fn ensure_len(&mut self, end: usize) {
+        if self.buffer.len() < end {
+            self.buffer.resize(end, 0);
+        }
+    }
+
     pub fn write(&mut self, v: u8) {
+        self.ensure_len(self.pos + 1);
         self.buffer[self.pos] = v;
         self.pos += 1;
     }
 
+    /// Write a bipolar value as stored by the M8, centered on 0x80
+    pub fn write_signed(&mut self, v: i8) {
+        self.write((v as i16 + 0x80) as u8)
+    }
+
     pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.ensure_len(self.pos + bytes.len());
         let mut cursor = self.pos;
         let buff = &mut self.buffer;
 
@@ -54,9 +82,69 @@ impl Writer {
             return;
         }
 
+        self.ensure_len(self.pos + until);
         for _i in 0..until {
             self.buffer[self.pos] = v;
             self.pos += 1;
         }
     }
 }
+
+/// Starting value of [`fnv1a`]
+pub(crate) const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// 64 bits FNV-1a hash of `bytes`, continuing from `hash`. Unlike the
+/// standard `DefaultHasher` the algorithm is fixed, so hashes can be
+/// stored and compared across runs and builds.
+pub(crate) fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |h, &b| {
+        (h ^ b as u64).wrapping_mul(0x0000_0100_0000_01B3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::writer::*;
+
+    #[test]
+    fn fnv1a_reference_value() {
+        assert_eq!(fnv1a(FNV_OFFSET, b""), FNV_OFFSET);
+        assert_eq!(fnv1a(FNV_OFFSET, b"a"), 0xaf63_dc4c_8601_ec8c);
+    }
+
+    #[test]
+    fn written_bytes_are_retrievable() {
+        let mut w = Writer::with_capacity(16);
+        w.write(0x42);
+        w.write_string("M8", 4);
+        w.seek(8);
+        w.write_bytes(&[1, 2]);
+
+        assert_eq!(w.as_slice(), &[0x42, b'M', b'8', 0, 0, 0, 0, 0, 1, 2]);
+        assert_eq!(w.finish().len(), 10);
+    }
+
+    #[test]
+    fn preloaded_buffer_is_overwritten() {
+        let mut w = Writer::new(vec![0xFF; 4]);
+        w.skip(1);
+        w.write(0);
+        assert_eq!(w.finish(), vec![0xFF, 0, 0xFF, 0xFF]);
+    }
+
+    #[test]
+    fn signed_values_round_trip() {
+        let mut w = Writer::with_capacity(3);
+        w.write_signed(0);
+        w.write_signed(-128);
+        w.write_signed(127);
+
+        let bytes = w.finish();
+        assert_eq!(bytes, vec![0x80, 0x00, 0xFF]);
+
+        let mut reader = crate::reader::Reader::new(bytes);
+        assert_eq!(reader.read_signed(), 0);
+        assert_eq!(reader.read_signed(), -128);
+        assert_eq!(reader.read_signed(), 127);
+    }
+}